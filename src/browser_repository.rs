@@ -409,6 +409,7 @@ impl SupportedAppRepository {
             snap_app_config_dir_absolute: snap_app_config_dir_absolute,
             macos_sandbox_app_config_dir_absolute: macos_sandbox_app_config_dir_absolute,
             find_profiles_fn: Some(chromium_profiles_parser::find_chromium_profiles),
+            restricted_url_patterns: vec![],
             restricted_url_matchers: vec![],
             profile_args_fn: |profile_cli_arg_value| {
                 vec![format!("--profile-directory={}", profile_cli_arg_value)]
@@ -447,6 +448,7 @@ impl SupportedAppRepository {
             snap_app_config_dir_absolute: snap_app_config_dir_absolute,
             macos_sandbox_app_config_dir_absolute: macos_sandbox_app_config_dir_absolute,
             find_profiles_fn: Some(firefox_profiles_parser::find_firefox_profiles),
+            restricted_url_patterns: vec![],
             restricted_url_matchers: vec![],
             profile_args_fn: |profile_cli_arg_value| {
                 vec!["-P".to_string(), profile_cli_arg_value.to_string()]
@@ -467,7 +469,7 @@ impl SupportedAppRepository {
         url_transform_fn: UrlTransformFn,
     ) -> SupportedApp {
         let restricted_url_matchers =
-            Self::generate_restricted_hostname_matchers(&restricted_domain_patterns);
+            url_rule::to_restricted_url_matchers(&restricted_domain_patterns);
 
         SupportedApp {
             app_id: app_id,
@@ -475,6 +477,7 @@ impl SupportedAppRepository {
             snap_app_config_dir_absolute: PathBuf::new(),
             macos_sandbox_app_config_dir_absolute: PathBuf::new(),
             find_profiles_fn: None,
+            restricted_url_patterns: restricted_domain_patterns,
             restricted_url_matchers: restricted_url_matchers,
             profile_args_fn: |_profile_cli_arg_value| vec![],
             incognito_args: vec![],
@@ -483,21 +486,6 @@ impl SupportedAppRepository {
         }
     }
 
-    fn generate_restricted_hostname_matchers(
-        restricted_domains: &Vec<String>,
-    ) -> Vec<UrlGlobMatcher> {
-        let restricted_hostname_matchers: Vec<UrlGlobMatcher> = restricted_domains
-            .iter()
-            .map(|url_pattern| {
-                let url_matcher = url_rule::to_url_matcher(url_pattern.as_str());
-                let glob_matcher = url_matcher.to_glob_matcher();
-                glob_matcher
-            })
-            .collect();
-
-        return restricted_hostname_matchers;
-    }
-
     fn linear_app() -> SupportedApp {
         let app_id = AppIdentifier::new("com.linear", "NOLINUXAPPEXISTS.desktop", "TODOWINDOWS");
 
@@ -545,7 +533,7 @@ impl SupportedAppRepository {
             "*.enterprise.slack.com".to_string(),
         ];
         let restricted_url_matchers =
-            Self::generate_restricted_hostname_matchers(&restricted_domain_patterns);
+            url_rule::to_restricted_url_matchers(&restricted_domain_patterns);
 
         SupportedApp {
             app_id: app_id,
@@ -553,6 +541,7 @@ impl SupportedAppRepository {
             snap_app_config_dir_absolute: snap_app_config_dir_absolute,
             macos_sandbox_app_config_dir_absolute: macos_sandbox_app_config_dir_absolute,
             find_profiles_fn: Some(slack_profiles_parser::find_slack_profiles),
+            restricted_url_patterns: restricted_domain_patterns,
             restricted_url_matchers: restricted_url_matchers,
             profile_args_fn: |_profile_cli_arg_value| vec![],
             incognito_args: vec![],
@@ -600,6 +589,7 @@ pub struct SupportedApp {
     app_config_dir_absolute: PathBuf,
     snap_app_config_dir_absolute: PathBuf,
     macos_sandbox_app_config_dir_absolute: PathBuf,
+    restricted_url_patterns: Vec<String>,
     restricted_url_matchers: Vec<UrlGlobMatcher>,
     find_profiles_fn: Option<
         fn(
@@ -640,6 +630,10 @@ impl SupportedApp {
         };
     }
 
+    pub fn get_restricted_url_patterns(&self) -> &Vec<String> {
+        return &self.restricted_url_patterns;
+    }
+
     pub fn get_restricted_hostname_matchers(&self) -> &Vec<UrlGlobMatcher> {
         return &self.restricted_url_matchers;
     }
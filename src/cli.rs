@@ -0,0 +1,1051 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::Serialize;
+use tracing::info;
+use url::Url;
+
+use crate::config_formats::ConfigFormat;
+use crate::config_migrations::ConfigError;
+use crate::opening_rules::{OpeningRule, RulesValidationReport};
+use crate::rule_actions::RuleAction;
+use crate::rule_conditions::{Clock, LazySourceApp, SystemClock, SystemEnvironment};
+use crate::settings_bundle::ImportMode;
+use crate::utils::{Config, OSAppFinder, ProfileAndOptions, RuleMatching};
+use crate::{
+    config_layers, config_migrations, generate_all_browser_profiles,
+    generate_browser_profiles_for_config, get_browser_profile_by_id,
+    get_rule_for_source_app_and_url, paths, profile_ids, rule_actions, settings_bundle, utils,
+    CommonBrowserProfile,
+};
+
+const EXAMPLE_URL: &'static str = "https://example.com";
+
+#[derive(Debug, PartialEq)]
+pub enum CliCommand {
+    // no subcommand given, e.g `browsers https://example.com --no-gui`
+    // kept for .desktop files, installers and OS integrations which launch us with just the url
+    Gui {
+        url: String,
+        show_gui: bool,
+        force_reload: bool,
+    },
+    Open {
+        url: String,
+        profile: Option<String>,
+        incognito: bool,
+        source_app: Option<String>,
+        // print the command instead of launching it
+        dry_run: bool,
+    },
+    List {
+        json: bool,
+        // url used to render the launch command of each profile
+        url: String,
+    },
+    Refresh,
+    Default(DefaultBrowserAction),
+    Rules(RulesAction),
+    Config(ConfigAction),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RulesAction {
+    // explain which rule matches the url and why
+    Test {
+        url: String,
+        source_app: Option<String>,
+    },
+    // list rules which are invalid and skipped
+    Validate,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigAction {
+    // effective config, merged from all config files
+    Show,
+    // config files in the order they are merged
+    Files,
+    // settings bundle for another machine, printed if no file is given
+    Export {
+        file: Option<String>,
+    },
+    Import {
+        file: String,
+        replace: bool,
+        // only show the conflict report
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DefaultBrowserAction {
+    Status,
+    Set,
+    Unset,
+}
+
+fn build_cli() -> Command {
+    return Command::new("browsers")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Open the right browser at the right time")
+        .args_conflicts_with_subcommands(true)
+        .arg(Arg::new("url").value_name("URL").help("Url to open"))
+        .arg(
+            Arg::new("no-gui")
+                .long("no-gui")
+                .action(ArgAction::SetTrue)
+                .help("Don't show the browser picker, only apply the rules"),
+        )
+        .arg(
+            Arg::new("reload")
+                .long("reload")
+                .action(ArgAction::SetTrue)
+                .help("Look up installed browsers and profiles again"),
+        )
+        .subcommand(
+            Command::new("open")
+                .about("Open url in a profile, by rules or by showing the browser picker")
+                .arg(Arg::new("url").value_name("URL").required(true))
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .value_name("UNIQUE_ID")
+                        .help("Open in this profile (see `browsers list`) without the picker"),
+                )
+                .arg(
+                    Arg::new("incognito")
+                        .long("incognito")
+                        .action(ArgAction::SetTrue)
+                        .requires("profile")
+                        .help("Open in incognito/private mode"),
+                )
+                .arg(
+                    Arg::new("source-app")
+                        .long("source-app")
+                        .value_name("APP_ID")
+                        .help("App which opened the url, used for matching rules"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the command which would be run, without launching it"),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List visible and hidden profiles with their ids")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Output as json, including the command used to open a url"),
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .default_value(EXAMPLE_URL)
+                        .help("Url used in the command of each profile in json output"),
+                ),
+        )
+        .subcommand(Command::new("refresh").about("Look up installed browsers and profiles again"))
+        .subcommand(
+            Command::new("default")
+                .about("Check or change if Browsers is the default web browser")
+                .subcommand_required(true)
+                .subcommand(Command::new("status").about("Show if Browsers is the default"))
+                .subcommand(Command::new("set").about("Make Browsers the default"))
+                .subcommand(
+                    Command::new("unset").about("Restore the previous default web browser"),
+                ),
+        )
+        .subcommand(
+            Command::new("rules")
+                .about("Work with rules from config.json")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("test")
+                        .about("Show which rules match the url and why")
+                        .arg(Arg::new("url").value_name("URL").required(true))
+                        .arg(
+                            Arg::new("source-app")
+                                .long("source-app")
+                                .value_name("APP_ID")
+                                .help("App which opened the url"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("validate")
+                        .about("Show config errors and rules which are invalid and skipped"),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect config, which is merged from system and user config files")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show").about("Print the config used, merged from all files"),
+                )
+                .subcommand(
+                    Command::new("files")
+                        .about("List config files in the order they are merged, and locked keys"),
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about("Save hidden apps and profiles, profile order, rules, default profile and ui settings for another machine")
+                        .arg(
+                            Arg::new("file")
+                                .value_name("FILE")
+                                .help("File to write (.json or .toml), printed as json if not given"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Apply settings exported on another machine, and show conflicts")
+                        .arg(Arg::new("file").value_name("FILE").required(true))
+                        .arg(
+                            Arg::new("replace")
+                                .long("replace")
+                                .action(ArgAction::SetTrue)
+                                .help("Replace local settings instead of merging, when both are set"),
+                        )
+                        .arg(
+                            Arg::new("dry-run")
+                                .long("dry-run")
+                                .action(ArgAction::SetTrue)
+                                .help("Show conflicts and changes without saving them"),
+                        ),
+                ),
+        );
+}
+
+// exits with usage message if arguments are not valid
+pub fn parse_args(args: Vec<String>) -> CliCommand {
+    // macOS adds process serial number when launched from Finder, e.g -psn_0_12345
+    let args: Vec<String> = args
+        .into_iter()
+        .filter(|arg| !arg.starts_with("-psn_"))
+        .collect();
+
+    let matches = build_cli().get_matches_from(args);
+    return to_cli_command(&matches);
+}
+
+fn to_cli_command(matches: &ArgMatches) -> CliCommand {
+    return match matches.subcommand() {
+        Some(("open", open_matches)) => CliCommand::Open {
+            url: open_matches.get_one::<String>("url").unwrap().to_string(),
+            profile: open_matches.get_one::<String>("profile").cloned(),
+            incognito: open_matches.get_flag("incognito"),
+            source_app: open_matches.get_one::<String>("source-app").cloned(),
+            dry_run: open_matches.get_flag("dry-run"),
+        },
+        Some(("list", list_matches)) => CliCommand::List {
+            json: list_matches.get_flag("json"),
+            url: list_matches.get_one::<String>("url").unwrap().to_string(),
+        },
+        Some(("refresh", _)) => CliCommand::Refresh,
+        Some(("default", default_matches)) => {
+            let action = match default_matches.subcommand_name() {
+                Some("set") => DefaultBrowserAction::Set,
+                Some("unset") => DefaultBrowserAction::Unset,
+                _ => DefaultBrowserAction::Status,
+            };
+            CliCommand::Default(action)
+        }
+        Some(("rules", rules_matches)) => match rules_matches.subcommand() {
+            Some(("test", test_matches)) => CliCommand::Rules(RulesAction::Test {
+                url: test_matches.get_one::<String>("url").unwrap().to_string(),
+                source_app: test_matches.get_one::<String>("source-app").cloned(),
+            }),
+            _ => CliCommand::Rules(RulesAction::Validate),
+        },
+        Some(("config", config_matches)) => match config_matches.subcommand() {
+            Some(("files", _)) => CliCommand::Config(ConfigAction::Files),
+            Some(("export", export_matches)) => CliCommand::Config(ConfigAction::Export {
+                file: export_matches.get_one::<String>("file").cloned(),
+            }),
+            Some(("import", import_matches)) => CliCommand::Config(ConfigAction::Import {
+                file: import_matches
+                    .get_one::<String>("file")
+                    .unwrap()
+                    .to_string(),
+                replace: import_matches.get_flag("replace"),
+                dry_run: import_matches.get_flag("dry-run"),
+            }),
+            _ => CliCommand::Config(ConfigAction::Show),
+        },
+        _ => CliCommand::Gui {
+            url: matches
+                .get_one::<String>("url")
+                .cloned()
+                .unwrap_or_default(),
+            show_gui: !matches.get_flag("no-gui"),
+            force_reload: matches.get_flag("reload"),
+        },
+    };
+}
+
+// runs commands which don't need the gui; returns process exit code
+pub fn run_command(cli_command: CliCommand) -> i32 {
+    info!("Running command {:?}", cli_command);
+
+    return match cli_command {
+        CliCommand::Open {
+            url,
+            profile,
+            incognito,
+            source_app,
+            dry_run: true,
+        } => print_open_command(url.as_str(), profile, incognito, source_app),
+        CliCommand::Open {
+            url,
+            profile: Some(profile_id),
+            incognito,
+            source_app: _,
+            dry_run: false,
+        } => open_in_profile(url.as_str(), profile_id.as_str(), incognito),
+        // clap already requires --profile for --incognito, the picker has no incognito mode
+        CliCommand::Open {
+            profile: None,
+            incognito: true,
+            dry_run: false,
+            ..
+        } => {
+            eprintln!("--incognito can only be used together with --profile");
+            1
+        }
+        CliCommand::List { json, url } => list_profiles(json, url.as_str()),
+        CliCommand::Refresh => refresh(),
+        CliCommand::Default(action) => default_web_browser(action),
+        CliCommand::Rules(RulesAction::Test { url, source_app }) => {
+            test_rules(url.as_str(), source_app)
+        }
+        CliCommand::Rules(RulesAction::Validate) => validate_rules(),
+        CliCommand::Config(ConfigAction::Show) => show_config(),
+        CliCommand::Config(ConfigAction::Files) => list_config_files(),
+        CliCommand::Config(ConfigAction::Export { file }) => export_settings(file),
+        CliCommand::Config(ConfigAction::Import {
+            file,
+            replace,
+            dry_run,
+        }) => import_settings(file.as_str(), replace, dry_run),
+        _ => {
+            eprintln!("This command requires the gui");
+            1
+        }
+    };
+}
+
+// Same as generate_all_browser_profiles(), but if config files can't be read, it's printed,
+// as notifications are for the gui
+fn generate_cli_browser_profiles(
+    app_finder: &OSAppFinder,
+    force_reload: bool,
+) -> (
+    Vec<OpeningRule>,
+    Option<ProfileAndOptions>,
+    Vec<CommonBrowserProfile>,
+    Vec<CommonBrowserProfile>,
+    RulesValidationReport,
+) {
+    let config = load_cli_config(app_finder);
+    return generate_browser_profiles_for_config(app_finder, config, force_reload);
+}
+
+fn load_cli_config(app_finder: &OSAppFinder) -> Config {
+    let (config, config_error_maybe) = app_finder.get_installed_browsers_config_and_error();
+    if let Some(config_error) = config_error_maybe {
+        eprintln!(
+            "Could not read config: {}",
+            utils::describe_config_error(&config_error)
+        );
+    }
+    return config;
+}
+
+fn open_in_profile(url: &str, profile_id: &str, incognito: bool) -> i32 {
+    let app_finder = OSAppFinder::new();
+    let (_, _, visible_browser_profiles, hidden_browser_profiles, _) =
+        generate_cli_browser_profiles(&app_finder, false);
+
+    let profile_maybe = get_browser_profile_by_id(
+        visible_browser_profiles.as_slice(),
+        hidden_browser_profiles.as_slice(),
+        profile_id,
+    );
+    if profile_maybe.is_none() {
+        eprintln!("No profile found with id {}", profile_id);
+        eprintln!("Use `browsers list` to see available profiles");
+        return 1;
+    }
+    let profile = profile_maybe.unwrap();
+
+    let spawn_result = profile.create_command(url, incognito).spawn();
+    if spawn_result.is_err() {
+        eprintln!(
+            "Could not launch {}: {}",
+            profile.get_browser_name(),
+            spawn_result.unwrap_err()
+        );
+        return 1;
+    }
+
+    return 0;
+}
+
+// resolves the profile like a real launch would (explicit profile, rules, default profile),
+// but only prints the resulting command
+fn print_open_command(
+    url: &str,
+    profile_id_maybe: Option<String>,
+    incognito: bool,
+    source_app_maybe: Option<String>,
+) -> i32 {
+    let app_finder = OSAppFinder::new();
+    let (opening_rules, default_profile, visible_browser_profiles, hidden_browser_profiles, _) =
+        generate_cli_browser_profiles(&app_finder, false);
+
+    let (profile_id, incognito) = if let Some(profile_id) = profile_id_maybe {
+        (profile_id, incognito)
+    } else {
+        let rule_action_maybe = get_rule_for_source_app_and_url(
+            &opening_rules,
+            default_profile,
+            url,
+            &LazySourceApp::known(source_app_maybe),
+            &SystemClock,
+            &SystemEnvironment,
+        );
+        if rule_action_maybe.is_none() {
+            println!("No rule or default profile matched, the browser picker would be shown");
+            return 0;
+        }
+        match rule_action_maybe.unwrap() {
+            RuleAction::OpenProfile { profile, incognito } => {
+                let candidates = profile.to_vec();
+                let existing_profile_id_maybe = candidates.iter().find(|profile_id| {
+                    get_browser_profile_by_id(
+                        visible_browser_profiles.as_slice(),
+                        hidden_browser_profiles.as_slice(),
+                        profile_id.as_str(),
+                    )
+                    .is_some()
+                });
+                for profile_id in candidates.iter() {
+                    if Some(profile_id) == existing_profile_id_maybe {
+                        break;
+                    }
+                    println!("profile {} doesn't exist, trying the next one", profile_id);
+                }
+                if existing_profile_id_maybe.is_none() {
+                    println!(
+                        "None of profiles {} exist, the browser picker would be shown",
+                        profile
+                    );
+                    return 0;
+                }
+                (existing_profile_id_maybe.unwrap().clone(), incognito)
+            }
+            RuleAction::RunCommand { command } => {
+                let command_with_url = rule_actions::command_with_url(&command, url);
+                println!("action: run_command");
+                println!("program: {}", command_with_url[0]);
+                println!("args: {:?}", &command_with_url[1..]);
+                return 0;
+            }
+            rule_action => {
+                println!("action: {}", rule_action.name());
+                println!("{}", rule_action.description());
+                return 0;
+            }
+        }
+    };
+
+    let profile_maybe = get_browser_profile_by_id(
+        visible_browser_profiles.as_slice(),
+        hidden_browser_profiles.as_slice(),
+        profile_id.as_str(),
+    );
+    if profile_maybe.is_none() {
+        eprintln!("No profile found with id {}", profile_id);
+        eprintln!("Use `browsers list` to see available profiles");
+        return 1;
+    }
+    let profile = profile_maybe.unwrap();
+
+    let transformed_url = profile
+        .get_browser_common()
+        .supported_app
+        .get_transformed_url(profile, url);
+    let command = profile.create_command(url, incognito);
+    let launch_command = LaunchCommand::from_command(&command);
+
+    println!("profile: {}", profile.get_unique_id());
+    println!("incognito: {}", incognito);
+    println!("url: {}", transformed_url);
+    println!("program: {}", launch_command.program);
+    println!("args: {:?}", launch_command.args);
+    for (key, value_maybe) in launch_command.env {
+        match value_maybe {
+            Some(value) => println!("env: {}={}", key, value),
+            None => println!("env: unset {}", key),
+        }
+    }
+
+    return 0;
+}
+
+// stable format for scripts, e.g to find ids for rules in config.json
+#[derive(Serialize, Debug)]
+struct ProfileListing {
+    unique_id: String,
+    unique_app_id: String,
+    display_name: String,
+    profile_name: String,
+    hidden: bool,
+    restricted_url_patterns: Vec<String>,
+    command: LaunchCommand,
+}
+
+#[derive(Serialize, Debug)]
+struct LaunchCommand {
+    program: String,
+    args: Vec<String>,
+    // only variables which are explicitly set for the command (None if removed)
+    env: BTreeMap<String, Option<String>>,
+}
+
+impl LaunchCommand {
+    fn from_command(command: &process::Command) -> Self {
+        let program = command.get_program().to_string_lossy().to_string();
+        let args = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        let env = command
+            .get_envs()
+            .map(|(key, value_maybe)| {
+                (
+                    key.to_string_lossy().to_string(),
+                    value_maybe.map(|value| value.to_string_lossy().to_string()),
+                )
+            })
+            .collect();
+
+        return Self {
+            program: program,
+            args: args,
+            env: env,
+        };
+    }
+}
+
+impl ProfileListing {
+    fn new(profile: &CommonBrowserProfile, hidden: bool, url: &str) -> Self {
+        let command = profile.create_command(url, false);
+
+        return Self {
+            unique_id: profile.get_unique_id(),
+            unique_app_id: profile.get_unique_app_id(),
+            display_name: profile.get_browser_name().to_string(),
+            profile_name: profile.get_profile_name().to_string(),
+            hidden: hidden,
+            restricted_url_patterns: profile.get_restricted_url_patterns().clone(),
+            command: LaunchCommand::from_command(&command),
+        };
+    }
+}
+
+fn list_profiles(json: bool, url: &str) -> i32 {
+    let app_finder = OSAppFinder::new();
+    let (_, _, visible_browser_profiles, hidden_browser_profiles, _) =
+        generate_cli_browser_profiles(&app_finder, false);
+
+    if json {
+        let listings: Vec<ProfileListing> = visible_browser_profiles
+            .iter()
+            .map(|profile| ProfileListing::new(profile, false, url))
+            .chain(
+                hidden_browser_profiles
+                    .iter()
+                    .map(|profile| ProfileListing::new(profile, true, url)),
+            )
+            .collect();
+
+        println!("{}", serde_json::to_string_pretty(&listings).unwrap());
+        return 0;
+    }
+
+    for (profiles, visibility) in [
+        (visible_browser_profiles, "visible"),
+        (hidden_browser_profiles, "hidden"),
+    ] {
+        for profile in profiles {
+            let mut full_name = profile.get_browser_name().to_string();
+            if profile.get_browser_common().supports_profiles() {
+                full_name = full_name + " " + profile.get_profile_name();
+            }
+            println!("{}\t{}\t{}", profile.get_unique_id(), visibility, full_name);
+        }
+    }
+
+    return 0;
+}
+
+fn refresh() -> i32 {
+    let app_finder = OSAppFinder::new();
+    let (_, _, visible_browser_profiles, hidden_browser_profiles, _) =
+        generate_cli_browser_profiles(&app_finder, true);
+
+    println!(
+        "Found {} profiles ({} hidden)",
+        visible_browser_profiles.len() + hidden_browser_profiles.len(),
+        hidden_browser_profiles.len()
+    );
+    return 0;
+}
+
+fn default_web_browser(action: DefaultBrowserAction) -> i32 {
+    match action {
+        DefaultBrowserAction::Status => {
+            if utils::is_default_web_browser() {
+                println!("Browsers is the default web browser");
+            } else {
+                println!("Browsers is not the default web browser");
+            }
+        }
+        DefaultBrowserAction::Set => {
+            let was_default = utils::set_as_default_web_browser();
+            if was_default {
+                println!("Browsers is already the default web browser");
+            } else {
+                println!("Browsers is now the default web browser");
+            }
+        }
+        DefaultBrowserAction::Unset => {
+            utils::unset_as_default_web_browser();
+            println!("Restored the previous default web browser");
+        }
+    }
+
+    return 0;
+}
+
+// prints evaluation of every rule in order, and which profile would be used
+fn test_rules(url: &str, source_app_maybe: Option<String>) -> i32 {
+    let url_result = Url::from_str(url);
+    if url_result.is_err() {
+        eprintln!("Not a valid url: {} ({})", url, url_result.unwrap_err());
+        return 1;
+    }
+    let given_url = url_result.unwrap();
+
+    let app_finder = OSAppFinder::new();
+    let config = load_cli_config(&app_finder);
+    let rule_matching = config.get_rule_matching();
+    let (opening_rules, default_profile_maybe, _, _, report) =
+        generate_browser_profiles_for_config(&app_finder, config, false);
+
+    println!("url: {}", given_url.as_str());
+    println!(
+        "source app: {}",
+        source_app_maybe.as_deref().unwrap_or("(unknown)")
+    );
+    println!();
+
+    for line in report.summary_lines() {
+        println!("{}", line);
+    }
+    println!(
+        "rules are checked in order of priority{}",
+        if rule_matching == RuleMatching::MostSpecific {
+            ", then specificity (rule_matching is most_specific)"
+        } else {
+            ", then order in config (rule_matching is first_match)"
+        }
+    );
+
+    let mut matched_rule_maybe: Option<(usize, RuleAction)> = None;
+    let now = SystemClock.now();
+
+    for rule in opening_rules.iter() {
+        let rule_number = rule.get_index() + 1;
+        let rule_evaluation =
+            rule.evaluate(&given_url, source_app_maybe.as_ref(), &now, &SystemEnvironment);
+
+        let mut url_pattern_description = if let Some(url_regex) = rule.get_url_regex() {
+            format!("url_regex={}", serde_json::to_string(url_regex).unwrap())
+        } else {
+            format!(
+                "url_pattern={}",
+                rule.get_url_pattern().map_or("(any)", |p| p.as_str())
+            )
+        };
+        if !rule.get_query_params().is_empty() {
+            url_pattern_description += format!(
+                " query_params={}",
+                serde_json::to_string(rule.get_query_params()).unwrap()
+            )
+            .as_str();
+        }
+
+        let source_app_description = if let Some(not_source_app) = rule.get_not_source_app() {
+            format!(
+                "source_app={} not_source_app={}",
+                rule.get_source_app()
+                    .map_or("(any)".to_string(), |a| a.to_string()),
+                not_source_app
+            )
+        } else {
+            format!(
+                "source_app={}",
+                rule.get_source_app()
+                    .map_or("(any)".to_string(), |a| a.to_string())
+            )
+        };
+
+        let specificity = rule.get_specificity();
+        println!(
+            "rule #{}: {} {} priority={} specificity={}/{} -> {}",
+            rule_number,
+            url_pattern_description,
+            source_app_description,
+            rule.get_priority(),
+            specificity.url_pattern,
+            specificity.other_conditions,
+            rule.get_action().description()
+        );
+
+        if let Some(ref url_match) = rule_evaluation.url_match {
+            let parts: Vec<String> = url_match
+                .parts()
+                .iter()
+                .map(|(part, matches)| format!("{}={}", part.as_str(), yes_no(*matches)))
+                .collect();
+            println!("  url: {}", parts.join(" "));
+        } else {
+            println!("  url: no url_pattern, url_regex or query_params, matches any url");
+        }
+        println!("  source_app: {}", yes_no(rule_evaluation.source_app_match));
+        if let Some(ref condition_matches) = rule_evaluation.environment_match {
+            let conditions: Vec<String> = condition_matches
+                .iter()
+                .map(|condition_match| {
+                    format!(
+                        "{}={}",
+                        condition_match.condition,
+                        yes_no(condition_match.matches)
+                    )
+                })
+                .collect();
+            println!("  environment: {}", conditions.join(" "));
+        }
+        if rule.get_schedule().is_some() {
+            println!(
+                "  schedule: {}",
+                if rule_evaluation.schedule_match {
+                    "active now"
+                } else {
+                    "not active now"
+                }
+            );
+        }
+
+        let is_match = rule_evaluation.is_match();
+        if is_match && matched_rule_maybe.is_none() {
+            println!("  => MATCH, this rule is used");
+            matched_rule_maybe = Some((rule_number, rule.get_action().clone()));
+        } else if is_match {
+            println!("  => match, but a rule above is used");
+        } else {
+            println!("  => no match");
+        }
+    }
+
+    if opening_rules.is_empty() {
+        println!("no rules in config");
+    }
+    println!();
+
+    if let Some((rule_number, rule_action)) = matched_rule_maybe {
+        println!(
+            "decision: rule #{} -> {}",
+            rule_number,
+            rule_action.description()
+        );
+    } else if let Some(default_profile) = default_profile_maybe {
+        println!(
+            "decision: no rule matched, default_profile opens in {} (incognito={})",
+            default_profile.profile, default_profile.incognito
+        );
+    } else {
+        println!("decision: no rule matched and no default_profile, the browser picker is shown");
+    }
+
+    return 0;
+}
+
+fn validate_rules() -> i32 {
+    let app_finder = OSAppFinder::new();
+    let config_result = app_finder.load_installed_browsers_config();
+    if config_result.is_err() {
+        println!("{}", config_result.unwrap_err());
+        return 1;
+    }
+
+    let (_, _, _, _, report) = generate_all_browser_profiles(&app_finder, false);
+
+    for line in report.summary_lines() {
+        println!("{}", line);
+    }
+
+    if !report.is_valid() {
+        println!(
+            "{} of {} rules are invalid",
+            report.invalid_rules.len(),
+            report.rules_count
+        );
+        return 1;
+    }
+
+    println!("All {} rules are valid", report.rules_count);
+    return 0;
+}
+
+fn show_config() -> i32 {
+    let app_finder = OSAppFinder::new();
+    let config_result = app_finder.load_installed_browsers_config();
+    if config_result.is_err() {
+        eprintln!("{}", config_result.unwrap_err());
+        return 1;
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&config_result.unwrap()).unwrap()
+    );
+    return 0;
+}
+
+fn list_config_files() -> i32 {
+    let (layers, errors) = config_layers::load_system_layers();
+    for error in errors {
+        eprintln!("Skipped {}", error);
+    }
+
+    for layer in layers {
+        let locked_keys = layer.get_locked_keys();
+        if locked_keys.is_empty() {
+            println!("{}\tsystem", layer.path.display());
+        } else {
+            println!(
+                "{}\tsystem\tlocked: {}",
+                layer.path.display(),
+                locked_keys.join(", ")
+            );
+        }
+    }
+    // changes made in Browsers are saved here
+    println!("{}\tuser", paths::get_config_file_path().display());
+    return 0;
+}
+
+fn export_settings(file_maybe: Option<String>) -> i32 {
+    let app_finder = OSAppFinder::new();
+    // migrates legacy ids in config first, as they can't be made portable
+    generate_all_browser_profiles(&app_finder, false);
+    // only the user's settings, system config files are managed separately on each machine
+    let config_result = app_finder.load_user_config();
+    if config_result.is_err() {
+        eprintln!("{}", config_result.unwrap_err());
+        return 1;
+    }
+    let bundle = settings_bundle::export_settings(&config_result.unwrap());
+
+    if file_maybe.is_none() {
+        println!("{}", serde_json::to_string_pretty(&bundle).unwrap());
+        return 0;
+    }
+    let bundle_path = PathBuf::from(file_maybe.unwrap());
+    let format = ConfigFormat::from_path(bundle_path.as_path()).unwrap_or(ConfigFormat::Json);
+
+    let write_result = format
+        .serialize_config(&bundle, None)
+        .and_then(|contents| fs::write(bundle_path.as_path(), contents).map_err(ConfigError::from));
+    if write_result.is_err() {
+        eprintln!(
+            "Could not export to {}: {}",
+            bundle_path.display(),
+            write_result.unwrap_err()
+        );
+        return 1;
+    }
+
+    println!("Exported settings to {}", bundle_path.display());
+    return 0;
+}
+
+fn import_settings(file: &str, replace: bool, dry_run: bool) -> i32 {
+    let bundle_path = PathBuf::from(file);
+    let format = ConfigFormat::from_path(bundle_path.as_path()).unwrap_or(ConfigFormat::Json);
+    let bundle_result = fs::read_to_string(bundle_path.as_path())
+        .map_err(ConfigError::from)
+        .and_then(|contents| config_migrations::parse_config(contents.as_str(), format));
+    if bundle_result.is_err() {
+        eprintln!("{}: {}", bundle_path.display(), bundle_result.unwrap_err());
+        return 1;
+    }
+    let bundle = bundle_result.unwrap().config;
+
+    let app_finder = OSAppFinder::new();
+    // migrates legacy ids in config first, so they match ids in the bundle
+    generate_all_browser_profiles(&app_finder, false);
+    let installed_browsers = app_finder.get_installed_browsers_cached(false);
+    let app_ids = profile_ids::stable_app_ids(&installed_browsers);
+    let mode = if replace {
+        ImportMode::Replace
+    } else {
+        ImportMode::Merge
+    };
+
+    let report_result = if dry_run {
+        app_finder.load_user_config().map(|mut config| {
+            settings_bundle::import_settings(&mut config, &bundle, &app_ids, mode)
+        })
+    } else {
+        app_finder.update_installed_browsers_config(|config| {
+            settings_bundle::import_settings(config, &bundle, &app_ids, mode)
+        })
+    };
+    if report_result.is_err() {
+        eprintln!("Could not import settings: {}", report_result.unwrap_err());
+        return 1;
+    }
+
+    for line in report_result.unwrap().summary_lines() {
+        println!("{}", line);
+    }
+    if dry_run {
+        println!("Nothing was changed (--dry-run)");
+    } else {
+        println!("Imported settings from {}", bundle_path.display());
+    }
+    return 0;
+}
+
+fn yes_no(value: bool) -> &'static str {
+    return if value { "yes" } else { "no" };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        return args.iter().map(|arg| arg.to_string()).collect();
+    }
+
+    #[test]
+    fn test_parse_args_legacy_url() {
+        assert_eq!(
+            parse_args(to_args(&["browsers", "https://example.com", "--no-gui"])),
+            CliCommand::Gui {
+                url: "https://example.com".to_string(),
+                show_gui: false,
+                force_reload: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_ignores_macos_process_serial_number() {
+        assert_eq!(
+            parse_args(to_args(&["browsers", "-psn_0_12345"])),
+            CliCommand::Gui {
+                url: "".to_string(),
+                show_gui: true,
+                force_reload: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_open() {
+        assert_eq!(
+            parse_args(to_args(&[
+                "browsers",
+                "open",
+                "https://example.com",
+                "--profile",
+                "/usr/bin/firefox#abc.default",
+                "--incognito",
+            ])),
+            CliCommand::Open {
+                url: "https://example.com".to_string(),
+                profile: Some("/usr/bin/firefox#abc.default".to_string()),
+                incognito: true,
+                source_app: None,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_rules_test() {
+        assert_eq!(
+            parse_args(to_args(&[
+                "browsers",
+                "rules",
+                "test",
+                "https://example.com",
+                "--source-app",
+                "com.tinyspeck.slackmacgap",
+            ])),
+            CliCommand::Rules(RulesAction::Test {
+                url: "https://example.com".to_string(),
+                source_app: Some("com.tinyspeck.slackmacgap".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_args_config() {
+        assert_eq!(
+            parse_args(to_args(&["browsers", "config", "files"])),
+            CliCommand::Config(ConfigAction::Files)
+        );
+    }
+
+    #[test]
+    fn test_parse_args_config_import() {
+        assert_eq!(
+            parse_args(to_args(&[
+                "browsers",
+                "config",
+                "import",
+                "settings.toml",
+                "--replace",
+            ])),
+            CliCommand::Config(ConfigAction::Import {
+                file: "settings.toml".to_string(),
+                replace: true,
+                dry_run: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_args_default() {
+        assert_eq!(
+            parse_args(to_args(&["browsers", "default", "unset"])),
+            CliCommand::Default(DefaultBrowserAction::Unset)
+        );
+    }
+}
@@ -34,6 +34,7 @@ struct SocketMessage {
 // returns SingleInstance, so that lock is held until end of program lifetime
 pub fn check_single_instance(
     url: &str,
+    get_opener: impl FnOnce() -> String,
     main_sender: Sender<MessageToMain>,
 ) -> (bool, SingleInstance) {
     let runtime_dir = paths::get_runtime_dir();
@@ -108,7 +109,7 @@ pub fn check_single_instance(
         let mut local_socket_stream = result.unwrap();
 
         let message = SocketMessage {
-            opener: "".to_string(),
+            opener: get_opener(),
             url: url.to_string(),
         };
 
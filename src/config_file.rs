@@ -0,0 +1,139 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use fs2::FileExt;
+use tracing::warn;
+
+// config.json.1 is the newest, config.json.3 the oldest
+const BACKUPS_COUNT: usize = 3;
+
+// Advisory lock around read-modify-write of config.json, as several instances of Browsers
+// (e.g the picker and a `browsers` command) can change it at the same time.
+// Unlocked when dropped.
+pub struct ConfigLock {
+    lock_file: File,
+}
+
+impl ConfigLock {
+    // blocks until other instances have released the lock
+    pub fn acquire(config_path: &Path) -> io::Result<Self> {
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(with_suffix(config_path, ".lock"))?;
+        lock_file.lock_exclusive()?;
+        return Ok(Self {
+            lock_file: lock_file,
+        });
+    }
+}
+
+impl Drop for ConfigLock {
+    fn drop(&mut self) {
+        let unlock_result = self.lock_file.unlock();
+        if unlock_result.is_err() {
+            warn!("Could not unlock config: {}", unlock_result.unwrap_err());
+        }
+    }
+}
+
+// Writes to a temporary file which then replaces the config, so a crash can't leave
+// a truncated config behind. The previous config is kept as the first backup.
+pub fn write_atomically(config_path: &Path, contents: &str) -> io::Result<()> {
+    let temp_path = with_suffix(config_path, ".tmp");
+    {
+        let mut temp_file = File::create(temp_path.as_path())?;
+        temp_file.write_all(contents.as_bytes())?;
+        temp_file.sync_all()?;
+    }
+
+    if config_path.exists() {
+        rotate_backups(config_path)?;
+    }
+
+    return fs::rename(temp_path.as_path(), config_path);
+}
+
+// config.json.2 -> config.json.3, config.json.1 -> config.json.2, config.json -> config.json.1
+fn rotate_backups(config_path: &Path) -> io::Result<()> {
+    for backup_number in (1..BACKUPS_COUNT).rev() {
+        let older_backup_path = backup_path(config_path, backup_number);
+        if older_backup_path.exists() {
+            fs::rename(older_backup_path, backup_path(config_path, backup_number + 1))?;
+        }
+    }
+
+    // copied, not renamed, so config.json always exists
+    fs::copy(config_path, backup_path(config_path, 1))?;
+    return Ok(());
+}
+
+fn backup_path(config_path: &Path, backup_number: usize) -> PathBuf {
+    return with_suffix(config_path, format!(".{}", backup_number).as_str());
+}
+
+// e.g config.json -> config.json.lock
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path_with_suffix = path.as_os_str().to_owned();
+    path_with_suffix.push(suffix);
+    return PathBuf::from(path_with_suffix);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    struct TempDir {
+        root: PathBuf,
+    }
+
+    impl TempDir {
+        fn new(test_name: &str) -> Self {
+            let root =
+                env::temp_dir().join(format!("browsers-{}-{}", test_name, std::process::id()));
+            fs::remove_dir_all(root.as_path()).ok();
+            fs::create_dir_all(root.as_path()).unwrap();
+            return Self { root: root };
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(self.root.as_path()).ok();
+        }
+    }
+
+    #[test]
+    fn test_write_atomically_rotates_backups() {
+        let temp_dir = TempDir::new("config-file-backups");
+        let config_path = temp_dir.root.join("config.json");
+
+        for version in 1..=5 {
+            write_atomically(config_path.as_path(), format!("{}", version).as_str()).unwrap();
+        }
+
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(read(config_path.clone()), "5");
+        assert_eq!(read(backup_path(config_path.as_path(), 1)), "4");
+        assert_eq!(read(backup_path(config_path.as_path(), 2)), "3");
+        assert_eq!(read(backup_path(config_path.as_path(), 3)), "2");
+        assert_eq!(backup_path(config_path.as_path(), 4).exists(), false);
+        assert_eq!(temp_dir.root.join("config.json.tmp").exists(), false);
+    }
+
+    #[test]
+    fn test_lock() {
+        let temp_dir = TempDir::new("config-file-lock");
+        let config_path = temp_dir.root.join("config.json");
+
+        let lock = ConfigLock::acquire(config_path.as_path()).unwrap();
+        let other_lock_file = File::open(temp_dir.root.join("config.json.lock")).unwrap();
+        assert_eq!(other_lock_file.try_lock_exclusive().is_err(), true);
+
+        drop(lock);
+        assert_eq!(other_lock_file.try_lock_exclusive().is_ok(), true);
+    }
+}
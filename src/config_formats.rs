@@ -0,0 +1,361 @@
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use toml_edit::{Document, Item, Table};
+
+use crate::config_migrations::ConfigError;
+use crate::utils::Config;
+
+// Config can be written in json, toml or yaml, the keys are the same in all of them.
+// In a config dir the first existing file in this list is used, others are ignored.
+// If there is none, config.json is created.
+pub const CONFIG_FILENAMES: [&'static str; 4] =
+    ["config.toml", "config.yaml", "config.yml", "config.json"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    // comments and formatting are kept when Browsers changes the file
+    Toml,
+    // read only, as comments would be lost when saving
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<ConfigFormat> {
+        let extension = path.extension()?.to_str()?;
+        return match extension {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+        };
+    }
+
+    pub fn can_save(&self) -> bool {
+        return *self != ConfigFormat::Yaml;
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self, contents: &str) -> Result<T, ConfigError> {
+        return match self {
+            ConfigFormat::Json => serde_json::from_str(contents).map_err(ConfigError::from),
+            ConfigFormat::Toml => {
+                toml_edit::de::from_str(contents).map_err(|error| toml_error(error, contents))
+            }
+            ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(yaml_error),
+        };
+    }
+
+    // Serializes config in this format. For toml, values which didn't change keep their
+    // comments and formatting from previous_contents.
+    pub fn serialize_config(
+        &self,
+        config: &Config,
+        previous_contents_maybe: Option<&str>,
+    ) -> Result<String, ConfigError> {
+        return match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(config).map_err(ConfigError::from),
+            ConfigFormat::Toml => serialize_toml(config, previous_contents_maybe),
+            ConfigFormat::Yaml => Err(ConfigError::ReadOnlyFormat(*self)),
+        };
+    }
+}
+
+// first existing config file in config_dir, see CONFIG_FILENAMES
+pub fn find_config_file(config_dir: &Path) -> Option<PathBuf> {
+    return CONFIG_FILENAMES
+        .iter()
+        .map(|config_filename| config_dir.join(config_filename))
+        .find(|config_path| config_path.is_file());
+}
+
+fn serialize_toml(
+    config: &Config,
+    previous_contents_maybe: Option<&str>,
+) -> Result<String, ConfigError> {
+    // parsed back, so nested values are written as [tables] and [[arrays of tables]]
+    let new_document: Document = toml_edit::ser::to_string_pretty(config)
+        .map_err(|error| ConfigError::Io(error.to_string()))?
+        .parse()
+        .map_err(|error: toml_edit::TomlError| ConfigError::Io(error.to_string()))?;
+    if previous_contents_maybe.is_none() {
+        return Ok(new_document.to_string());
+    }
+    let previous_contents = previous_contents_maybe.unwrap();
+
+    let document_result = previous_contents.parse::<Document>();
+    let old_config_result = ConfigFormat::Toml.parse::<Config>(previous_contents);
+    if document_result.is_err() || old_config_result.is_err() {
+        return Ok(new_document.to_string());
+    }
+    let mut document = document_result.unwrap();
+    // compared with defaults filled in, so defaults missing from the file are not added,
+    // except the version, which must be written once the file is migrated
+    let mut old_value = serde_json::to_value(old_config_result.unwrap())?;
+    old_value["version"] = document
+        .get("version")
+        .and_then(|version| version.as_integer())
+        .map_or(Value::Null, Value::from);
+    let new_value = serde_json::to_value(config)?;
+
+    update_toml_table(
+        document.as_table_mut(),
+        new_document.as_table(),
+        old_value.as_object().unwrap_or(&Map::new()),
+        new_value.as_object().unwrap_or(&Map::new()),
+    );
+    return Ok(document.to_string());
+}
+
+// Changes table to new_table, but only replaces values which are different,
+// so comments and formatting of the rest are kept.
+// old_object and new_object are the same tables as json, to compare values.
+fn update_toml_table(
+    table: &mut Table,
+    new_table: &Table,
+    old_object: &Map<String, Value>,
+    new_object: &Map<String, Value>,
+) {
+    let removed_keys: Vec<String> = table
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new_object.contains_key(key))
+        .collect();
+    for removed_key in removed_keys {
+        table.remove(removed_key.as_str());
+    }
+
+    for (key, new_item) in new_table.iter() {
+        let new_value = &new_object[key];
+        let old_value = old_object.get(key).unwrap_or(&Value::Null);
+        if old_value == new_value {
+            continue;
+        }
+
+        match (table.get_mut(key), new_item) {
+            (Some(Item::Table(child_table)), Item::Table(new_child_table))
+                if old_value.is_object() && new_value.is_object() =>
+            {
+                update_toml_table(
+                    child_table,
+                    new_child_table,
+                    old_value.as_object().unwrap(),
+                    new_value.as_object().unwrap(),
+                );
+            }
+            (Some(Item::ArrayOfTables(tables)), Item::ArrayOfTables(new_tables))
+                if old_value.is_array() && new_value.is_array() =>
+            {
+                let old_list = old_value.as_array().unwrap();
+                let new_list = new_value.as_array().unwrap();
+                for (index, new_child_table) in new_tables.iter().enumerate() {
+                    let old_child_maybe = old_list.get(index).and_then(|v| v.as_object());
+                    let new_child_maybe = new_list.get(index).and_then(|v| v.as_object());
+                    match (tables.get_mut(index), old_child_maybe, new_child_maybe) {
+                        (Some(child_table), Some(old_child), Some(new_child)) => {
+                            if old_child != new_child {
+                                update_toml_table(
+                                    child_table,
+                                    new_child_table,
+                                    old_child,
+                                    new_child,
+                                );
+                            }
+                        }
+                        (Some(child_table), _, _) => *child_table = new_child_table.clone(),
+                        (None, _, _) => tables.push(new_child_table.clone()),
+                    }
+                }
+                while tables.len() > new_tables.len() {
+                    tables.remove(tables.len() - 1);
+                }
+            }
+            (Some(Item::Value(value)), Item::Value(new_toml_value)) => {
+                // keeps comments after the value
+                let decor = value.decor().clone();
+                *value = new_toml_value.clone();
+                *value.decor_mut() = decor;
+            }
+            (_, new_item) => {
+                // indexing keeps comments before the key
+                table[key] = new_item.clone();
+            }
+        }
+    }
+}
+
+// toml errors have a byte range, shown as line and column like in json
+fn toml_error(error: toml_edit::de::Error, contents: &str) -> ConfigError {
+    let (line, column) = match error.span() {
+        Some(span) => {
+            let before_error = &contents[..span.start.min(contents.len())];
+            let line = before_error.matches('\n').count() + 1;
+            let line_start = before_error.rfind('\n').map_or(0, |index| index + 1);
+            let column = before_error[line_start..].chars().count() + 1;
+            (line, column)
+        }
+        None => (0, 0),
+    };
+
+    return ConfigError::Parse {
+        line: line,
+        column: column,
+        message: error.message().replace('\n', ", "),
+    };
+}
+
+fn yaml_error(error: serde_yaml::Error) -> ConfigError {
+    let (line, column) = error
+        .location()
+        .map_or((0, 0), |location| (location.line(), location.column()));
+
+    // serde_yaml adds the location to the message, it's shown separately
+    let location_text = format!(" at line {} column {}", line, column);
+    let message = error.to_string().replacen(location_text.as_str(), "", 1);
+
+    return ConfigError::Parse {
+        line: line,
+        column: column,
+        message: message,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_migrations;
+    use crate::rule_actions::ProfileCandidates;
+    use crate::utils::ConfigRule;
+
+    #[test]
+    fn test_parse_formats() {
+        let toml = r#"
+            # work links
+            hidden_apps = ["firefox.desktop"]
+
+            [[rules]]
+            url_pattern = "*.corp.example"
+            profile = "google-chrome.desktop#Default"
+        "#;
+        let yaml = r#"
+            # work links
+            hidden_apps: [firefox.desktop]
+            rules:
+              - url_pattern: "*.corp.example"
+                profile: google-chrome.desktop#Default
+        "#;
+
+        for (contents, format) in [(toml, ConfigFormat::Toml), (yaml, ConfigFormat::Yaml)] {
+            let parsed = config_migrations::parse_config(contents, format).unwrap();
+            assert_eq!(parsed.config.get_hidden_apps(), &vec!["firefox.desktop"]);
+            assert_eq!(
+                parsed.config.get_rules()[0].get_rule().unwrap().url_pattern,
+                Some("*.corp.example".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            ConfigFormat::Toml
+                .parse::<Config>("hidden_apps = [\"firefox.desktop\"]\nrules = 1\n")
+                .unwrap_err(),
+            ConfigError::Parse {
+                line: 2,
+                column: 9,
+                message: "invalid type: integer `1`, expected a sequence".to_string(),
+            }
+        );
+        assert_eq!(
+            ConfigFormat::Yaml
+                .parse::<Config>("hidden_apps: [firefox.desktop]\nrules: 1\n")
+                .unwrap_err()
+                .to_string(),
+            "line 2, column 8: rules: invalid type: integer `1`, expected a sequence"
+        );
+    }
+
+    #[test]
+    fn test_serialize_toml_keeps_comments() {
+        let previous_toml = r#"# managed by hand
+version = 1
+
+# never show these
+hidden_apps = ["epiphany.desktop"] # GNOME Web
+
+# work links
+[[rules]]
+url_pattern = "*.corp.example" # intranet
+profile = "google-chrome.desktop#Default"
+"#;
+        let mut config: Config = ConfigFormat::Toml.parse(previous_toml).unwrap();
+        config.hide_profile("firefox.desktop#default");
+        config.add_rule(ConfigRule {
+            url_pattern: Some("github.com".to_string()),
+            profile: ProfileCandidates::One("firefox.desktop#work".to_string()),
+            ..ConfigRule::default()
+        });
+
+        let toml = ConfigFormat::Toml
+            .serialize_config(&config, Some(previous_toml))
+            .unwrap();
+        assert_eq!(
+            toml,
+            r#"# managed by hand
+version = 1
+
+# never show these
+hidden_apps = ["epiphany.desktop"] # GNOME Web
+hidden_profiles = ["firefox.desktop#default"]
+
+# work links
+[[rules]]
+url_pattern = "*.corp.example" # intranet
+profile = "google-chrome.desktop#Default"
+
+[[rules]]
+url_pattern = "github.com"
+profile = "firefox.desktop#work"
+incognito = false
+priority = 0
+"#
+        );
+
+        let saved_config: Config = ConfigFormat::Toml.parse(toml.as_str()).unwrap();
+        assert_eq!(saved_config.get_rules().len(), 2);
+    }
+
+    #[test]
+    fn test_serialize_toml_adds_version() {
+        let previous_toml = "# no version yet\nhidden_apps = [\"epiphany.desktop\"]\n";
+        let config: Config = ConfigFormat::Toml.parse(previous_toml).unwrap();
+
+        let toml = ConfigFormat::Toml
+            .serialize_config(&config, Some(previous_toml))
+            .unwrap();
+        assert_eq!(
+            toml,
+            "# no version yet\nhidden_apps = [\"epiphany.desktop\"]\nversion = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_yaml_is_read_only() {
+        assert_eq!(
+            ConfigFormat::Yaml
+                .serialize_config(&Config::default(), None)
+                .unwrap_err(),
+            ConfigError::ReadOnlyFormat(ConfigFormat::Yaml)
+        );
+    }
+}
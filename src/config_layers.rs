@@ -0,0 +1,449 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use crate::config_formats::{self, ConfigFormat};
+use crate::config_migrations::{self, ConfigError, CONFIG_VERSION};
+use crate::{paths, profile_ids};
+
+// Config can be split into several files, merged in this order:
+//   1. config file in each system config dir, e.g /etc/xdg/software.Browsers/config.json
+//   2. config.d/*.json (or .toml, .yaml) in each system config dir, in alphabetical order
+//   3. config file in the user config dir, which is the only file Browsers changes
+// Config files can be json, toml or yaml, see config_formats.
+//
+// Merging a file into the ones before it:
+//   - rules are appended, so they still apply in order of priority,
+//     and rules from earlier files win between rules with the same priority
+//   - hidden_apps and hidden_profiles are joined, so apps and profiles hidden
+//     by a system file can't be shown again by the user
+//   - ui and legacy_id_aliases are merged key by key
+//   - everything else (e.g default_profile, profile_order) is replaced
+//   - null, empty lists and empty objects are the same as a missing key
+//
+// System files can list keys in "locked" (e.g ["rules", "ui.show_hotkeys"]),
+// these keys are then ignored in the files after them.
+
+pub const LOCKED_KEY: &'static str = "locked";
+const DROP_INS_DIR_NAME: &'static str = "config.d";
+const JOINED_LIST_KEYS: [&'static str; 3] = ["rules", "hidden_apps", "hidden_profiles"];
+const MERGED_OBJECT_KEYS: [&'static str; 2] = ["ui", "legacy_id_aliases"];
+
+#[derive(Debug)]
+pub struct ConfigLayer {
+    pub path: PathBuf,
+    // migrated json, with only the keys which are in the file
+    pub value: Value,
+    // only system files can lock keys
+    pub is_system: bool,
+}
+
+impl ConfigLayer {
+    pub fn get_locked_keys(&self) -> Vec<String> {
+        if !self.is_system {
+            return vec![];
+        }
+
+        return self
+            .value
+            .get(LOCKED_KEY)
+            .and_then(|locked| locked.as_array())
+            .map(|locked| {
+                locked
+                    .iter()
+                    .filter_map(|key| key.as_str())
+                    .map(|key| key.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+}
+
+// value in a file which was not used, because an earlier system file locked it
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoredChange {
+    pub path: PathBuf,
+    pub key: String,
+}
+
+// Reads config files from system config dirs, in the order they are merged.
+// Doesn't include the user's config file.
+// Files which can't be read are skipped (and returned as errors), so that a mistake in one
+// system file doesn't break the config of every user.
+pub fn load_system_layers() -> (Vec<ConfigLayer>, Vec<ConfigError>) {
+    let system_config_files = find_system_config_files(&paths::get_system_config_dirs());
+    return load_system_files(&system_config_files);
+}
+
+fn load_system_files(paths: &[PathBuf]) -> (Vec<ConfigLayer>, Vec<ConfigError>) {
+    let mut layers: Vec<ConfigLayer> = vec![];
+    let mut errors: Vec<ConfigError> = vec![];
+    for path in paths {
+        let layer_result = load_system_file(path);
+        if layer_result.is_err() {
+            errors.push(layer_result.unwrap_err().in_file(path.clone()));
+            continue;
+        }
+        layers.push(layer_result.unwrap());
+    }
+    return (layers, errors);
+}
+
+fn load_system_file(path: &Path) -> Result<ConfigLayer, ConfigError> {
+    let format = ConfigFormat::from_path(path).unwrap_or(ConfigFormat::Json);
+    let contents = fs::read_to_string(path)?;
+    let parsed = config_migrations::parse_config(contents.as_str(), format)?;
+    return Ok(ConfigLayer {
+        path: path.to_path_buf(),
+        value: parsed.value,
+        is_system: true,
+    });
+}
+
+// existing config files and config.d/* files in system config dirs,
+// dirs are in order of importance, the most important last
+fn find_system_config_files(system_config_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut config_files: Vec<PathBuf> = vec![];
+    for system_config_dir in system_config_dirs {
+        if let Some(config_path) = config_formats::find_config_file(system_config_dir) {
+            config_files.push(config_path);
+        }
+
+        let drop_ins_dir = system_config_dir.join(DROP_INS_DIR_NAME);
+        let mut drop_ins: Vec<PathBuf> = fs::read_dir(drop_ins_dir.as_path())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file() && ConfigFormat::from_path(path).is_some())
+                    .collect()
+            })
+            .unwrap_or_default();
+        drop_ins.sort();
+        config_files.extend(drop_ins);
+    }
+    return config_files;
+}
+
+// Merges layers in order, see the top of this file.
+// Returns the merged config json, and values which were ignored because they are locked.
+pub fn merge_layers(layers: &[ConfigLayer]) -> (Value, Vec<IgnoredChange>) {
+    let mut merged: Map<String, Value> = Map::new();
+    let mut locked_keys: Vec<String> = vec![];
+    let mut ignored_changes: Vec<IgnoredChange> = vec![];
+
+    for layer in layers {
+        let empty_object = Map::new();
+        let layer_object = layer.value.as_object().unwrap_or(&empty_object);
+
+        for (key, value) in layer_object {
+            if key == LOCKED_KEY || key == "version" || is_unset(value) {
+                continue;
+            }
+            if locked_keys.contains(key) {
+                ignored_changes.push(IgnoredChange {
+                    path: layer.path.clone(),
+                    key: key.clone(),
+                });
+                continue;
+            }
+
+            if MERGED_OBJECT_KEYS.contains(&key.as_str()) && value.is_object() {
+                let merged_value = merged
+                    .entry(key.clone())
+                    .or_insert(Value::Object(Map::new()));
+                if !merged_value.is_object() {
+                    *merged_value = Value::Object(Map::new());
+                }
+                let merged_object = merged_value.as_object_mut().unwrap();
+
+                for (inner_key, inner_value) in value.as_object().unwrap() {
+                    let full_key = format!("{}.{}", key, inner_key);
+                    if inner_value.is_null() {
+                        continue;
+                    }
+                    if locked_keys.contains(&full_key) {
+                        ignored_changes.push(IgnoredChange {
+                            path: layer.path.clone(),
+                            key: full_key,
+                        });
+                        continue;
+                    }
+                    merged_object.insert(inner_key.clone(), inner_value.clone());
+                }
+                continue;
+            }
+
+            if JOINED_LIST_KEYS.contains(&key.as_str()) && value.is_array() {
+                let merged_value = merged.entry(key.clone()).or_insert(Value::Array(vec![]));
+                if !merged_value.is_array() {
+                    *merged_value = Value::Array(vec![]);
+                }
+                let merged_list = merged_value.as_array_mut().unwrap();
+
+                for item in value.as_array().unwrap() {
+                    // the same rule can be useful twice, e.g with other conditions
+                    if key == "rules" || !merged_list.contains(item) {
+                        merged_list.push(item.clone());
+                    }
+                }
+                continue;
+            }
+
+            merged.insert(key.clone(), value.clone());
+        }
+
+        locked_keys.extend(layer.get_locked_keys());
+    }
+
+    merged.insert("version".to_string(), Value::from(CONFIG_VERSION));
+    return (Value::Object(merged), ignored_changes);
+}
+
+// Whether a system file hides the profile, or its whole app
+pub fn is_hidden_by_system_layers(layers: &[ConfigLayer], profile_id: &str) -> bool {
+    let (app_id, _) = profile_ids::split_app_id(profile_id);
+    return layers.iter().filter(|layer| layer.is_system).any(|layer| {
+        contains_id(&layer.value, "hidden_apps", app_id)
+            || contains_id(&layer.value, "hidden_profiles", profile_id)
+    });
+}
+
+fn contains_id(value: &Value, key: &str, id: &str) -> bool {
+    return value
+        .get(key)
+        .and_then(|ids| ids.as_array())
+        .map_or(false, |ids| ids.iter().any(|item| item.as_str() == Some(id)));
+}
+
+fn is_unset(value: &Value) -> bool {
+    return match value {
+        Value::Null => true,
+        Value::Array(list) => list.is_empty(),
+        Value::Object(object) => object.is_empty(),
+        _ => false,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn layer(path: &str, value: Value, is_system: bool) -> ConfigLayer {
+        return ConfigLayer {
+            path: PathBuf::from(path),
+            value: value,
+            is_system: is_system,
+        };
+    }
+
+    #[test]
+    fn test_merge_layers() {
+        let layers = vec![
+            layer(
+                "/etc/xdg/software.Browsers/config.json",
+                json!({
+                    "version": 1,
+                    "hidden_apps": ["epiphany.desktop"],
+                    "profile_order": ["google-chrome.desktop#Default"],
+                    "rules": [{"url_pattern": "*.corp.example", "profile": "google-chrome.desktop#Default"}],
+                    "ui": {"show_hotkeys": false}
+                }),
+                true,
+            ),
+            layer(
+                "/home/x/.config/software.Browsers/config.json",
+                json!({
+                    "version": 1,
+                    "hidden_apps": ["epiphany.desktop", "firefox.desktop"],
+                    "hidden_profiles": [],
+                    "profile_order": ["firefox.desktop#work"],
+                    "default_profile": null,
+                    "rules": [{"url_pattern": "github.com", "profile": "firefox.desktop#work"}],
+                    "ui": {"quit_on_lost_focus": true}
+                }),
+                false,
+            ),
+        ];
+
+        let (merged, ignored_changes) = merge_layers(&layers);
+        assert_eq!(
+            merged,
+            json!({
+                "version": CONFIG_VERSION,
+                "hidden_apps": ["epiphany.desktop", "firefox.desktop"],
+                "profile_order": ["firefox.desktop#work"],
+                "rules": [
+                    {"url_pattern": "*.corp.example", "profile": "google-chrome.desktop#Default"},
+                    {"url_pattern": "github.com", "profile": "firefox.desktop#work"}
+                ],
+                "ui": {"show_hotkeys": false, "quit_on_lost_focus": true}
+            })
+        );
+        assert_eq!(ignored_changes, vec![]);
+    }
+
+    #[test]
+    fn test_merge_layers_locked() {
+        let layers = vec![
+            layer(
+                "/etc/xdg/software.Browsers/config.json",
+                json!({
+                    "default_profile": {"profile": "google-chrome.desktop#Default"},
+                    "locked": ["default_profile", "ui.show_hotkeys"]
+                }),
+                true,
+            ),
+            layer(
+                "/etc/xdg/software.Browsers/config.d/10-corp.json",
+                json!({"ui": {"show_hotkeys": false}}),
+                true,
+            ),
+            layer(
+                "/home/x/.config/software.Browsers/config.json",
+                json!({
+                    "default_profile": {"profile": "firefox.desktop#work"},
+                    "ui": {"show_hotkeys": true, "quit_on_lost_focus": true},
+                    // locking is not allowed in user config
+                    "locked": ["ui"]
+                }),
+                false,
+            ),
+            layer(
+                "/home/x/.config/software.Browsers/other.json",
+                json!({"ui": {"quit_on_lost_focus": false}}),
+                false,
+            ),
+        ];
+
+        let (merged, ignored_changes) = merge_layers(&layers);
+        assert_eq!(
+            merged,
+            json!({
+                "version": CONFIG_VERSION,
+                "default_profile": {"profile": "google-chrome.desktop#Default"},
+                "ui": {"quit_on_lost_focus": false}
+            })
+        );
+        assert_eq!(
+            ignored_changes,
+            vec![
+                IgnoredChange {
+                    path: PathBuf::from("/etc/xdg/software.Browsers/config.d/10-corp.json"),
+                    key: "ui.show_hotkeys".to_string(),
+                },
+                IgnoredChange {
+                    path: PathBuf::from("/home/x/.config/software.Browsers/config.json"),
+                    key: "default_profile".to_string(),
+                },
+                IgnoredChange {
+                    path: PathBuf::from("/home/x/.config/software.Browsers/config.json"),
+                    key: "ui.show_hotkeys".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_hidden_by_system_layers() {
+        let layers = vec![
+            layer(
+                "/etc/xdg/software.Browsers/config.json",
+                json!({"hidden_apps": ["epiphany.desktop"]}),
+                true,
+            ),
+            layer(
+                "/etc/xdg/software.Browsers/config.d/10-corp.json",
+                json!({"hidden_profiles": ["google-chrome.desktop#Profile 1"]}),
+                true,
+            ),
+            layer(
+                "/home/x/.config/software.Browsers/config.json",
+                json!({"hidden_profiles": ["firefox.desktop#work"]}),
+                false,
+            ),
+        ];
+
+        assert_eq!(
+            is_hidden_by_system_layers(&layers, "google-chrome.desktop#Profile 1"),
+            true
+        );
+        assert_eq!(
+            is_hidden_by_system_layers(&layers, "epiphany.desktop#default"),
+            true
+        );
+        // hidden by the user, so it can be restored
+        assert_eq!(
+            is_hidden_by_system_layers(&layers, "firefox.desktop#work"),
+            false
+        );
+        assert_eq!(
+            is_hidden_by_system_layers(&layers, "google-chrome.desktop#Default"),
+            false
+        );
+    }
+
+    #[test]
+    fn test_load_system_files_skips_broken_files() {
+        let root = env::temp_dir().join(format!("browsers-system-files-{}", std::process::id()));
+        fs::create_dir_all(root.as_path()).unwrap();
+        let valid_path = root.join("config.json");
+        let broken_path = root.join("10-broken.json");
+        fs::write(valid_path.as_path(), r#"{"hidden_apps": ["epiphany.desktop"]}"#).unwrap();
+        fs::write(broken_path.as_path(), r#"{"hidden_apps": ["#).unwrap();
+
+        let (layers, errors) = load_system_files(&[broken_path.clone(), valid_path.clone()]);
+        fs::remove_dir_all(root.as_path()).ok();
+
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].path, valid_path);
+        assert_eq!(layers[0].value["hidden_apps"], json!(["epiphany.desktop"]));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0]
+                .to_string()
+                .starts_with(broken_path.display().to_string().as_str()),
+            true
+        );
+    }
+
+    #[test]
+    fn test_find_system_config_files() {
+        let root = env::temp_dir().join(format!("browsers-config-layers-{}", std::process::id()));
+        let etc_dir = root.join("etc");
+        let vendor_dir = root.join("vendor");
+        fs::create_dir_all(etc_dir.join("config.d")).unwrap();
+        fs::create_dir_all(vendor_dir.join("config.d")).unwrap();
+        for path in [
+            etc_dir.join("config.json"),
+            etc_dir.join("config.d").join("20-b.json"),
+            etc_dir.join("config.d").join("10-a.toml"),
+            etc_dir.join("config.d").join("README.txt"),
+            vendor_dir.join("config.toml"),
+            vendor_dir.join("config.json"),
+            vendor_dir.join("config.d").join("00-vendor.yaml"),
+        ] {
+            fs::write(path, "{}").unwrap();
+        }
+
+        let config_files = find_system_config_files(&[etc_dir.clone(), vendor_dir.clone()]);
+        fs::remove_dir_all(root.as_path()).ok();
+
+        assert_eq!(
+            config_files,
+            vec![
+                etc_dir.join("config.json"),
+                etc_dir.join("config.d").join("10-a.toml"),
+                etc_dir.join("config.d").join("20-b.json"),
+                // config.toml is used instead of config.json
+                vendor_dir.join("config.toml"),
+                vendor_dir.join("config.d").join("00-vendor.yaml"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+
+use crate::config_formats::ConfigFormat;
+use crate::utils::Config;
+
+// version of config.json written by this version of the app
+//   0: before the version field
+//   1: version field
+pub const CONFIG_VERSION: u32 = 1;
+
+type MigrationFn = fn(&mut Map<String, Value>);
+
+// MIGRATIONS[n] upgrades config from version n to version n+1,
+// the version field itself is updated after each step
+const MIGRATIONS: [MigrationFn; CONFIG_VERSION as usize] = [migrate_0_to_1];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    // invalid json, or valid json which doesn't match the config format;
+    // line and column are 0 when the location is not known
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // written by a newer version of the app
+    UnsupportedVersion(u64),
+    // the config in memory is a fallback for a config.json with errors
+    ReadOnly,
+    // config file is in a format which can't be saved without losing comments
+    ReadOnlyFormat(ConfigFormat),
+    Io(String),
+    // error in one of the config files
+    File {
+        path: PathBuf,
+        error: Box<ConfigError>,
+    },
+}
+
+#[derive(Debug)]
+pub struct ParsedConfig {
+    pub config: Config,
+    // migrated json, with only the keys which are in the file
+    pub value: Value,
+    // version the config was written in
+    pub version: u32,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Parse {
+                line,
+                column,
+                message,
+            } => {
+                if *line == 0 {
+                    write!(f, "{}", message)
+                } else {
+                    write!(f, "line {}, column {}: {}", line, column, message)
+                }
+            }
+            ConfigError::UnsupportedVersion(version) => write!(
+                f,
+                "config version {} is newer than supported version {}, please update Browsers",
+                version, CONFIG_VERSION
+            ),
+            ConfigError::ReadOnly => {
+                write!(f, "config.json has errors which need to be fixed first")
+            }
+            ConfigError::ReadOnlyFormat(format) => write!(
+                f,
+                "Browsers doesn't change {} config files, as comments in them would be lost; \
+                 please make the change yourself, or use config.toml or config.json",
+                format.name()
+            ),
+            ConfigError::Io(message) => write!(f, "{}", message),
+            ConfigError::File { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(error: serde_json::Error) -> Self {
+        // serde_json appends the location to the message, it's shown separately
+        let location_suffix = format!(" at line {} column {}", error.line(), error.column());
+        let message = error.to_string();
+        let message = message
+            .strip_suffix(location_suffix.as_str())
+            .unwrap_or(&message);
+
+        return ConfigError::Parse {
+            line: error.line(),
+            column: error.column(),
+            message: message.to_string(),
+        };
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(error: std::io::Error) -> Self {
+        return ConfigError::Io(error.to_string());
+    }
+}
+
+impl ConfigError {
+    pub fn in_file(self, path: PathBuf) -> Self {
+        return ConfigError::File {
+            path: path,
+            error: Box::new(self),
+        };
+    }
+}
+
+// Parses config file contents, migrating it from older versions
+pub fn parse_config(contents: &str, format: ConfigFormat) -> Result<ParsedConfig, ConfigError> {
+    let mut value: Value = format.parse(contents)?;
+    let version = migrate(&mut value)?;
+
+    let config_result: Result<Config, serde_json::Error> = serde_json::from_value(value.clone());
+    if config_result.is_err() {
+        // errors in the migrated json have no location, the original text gives one
+        // (e.g for a type error in a file from an older version)
+        let original_config_result: Result<Config, ConfigError> = format.parse(contents);
+        if original_config_result.is_err() {
+            return Err(original_config_result.unwrap_err());
+        }
+        return Err(ConfigError::from(config_result.unwrap_err()));
+    }
+    let config = config_result.unwrap();
+
+    return Ok(ParsedConfig {
+        config: config,
+        value: value,
+        version: version,
+    });
+}
+
+// returns the version the config was in before migrating
+fn migrate(value: &mut Value) -> Result<u32, ConfigError> {
+    let config_object_maybe = value.as_object_mut();
+    if config_object_maybe.is_none() {
+        return Err(ConfigError::Parse {
+            line: 1,
+            column: 1,
+            message: "config must be a json object".to_string(),
+        });
+    }
+    let config_object = config_object_maybe.unwrap();
+
+    let version = match config_object.get("version") {
+        None => 0,
+        Some(Value::Number(number)) if number.as_u64().is_some() => number.as_u64().unwrap(),
+        Some(_) => {
+            return Err(ConfigError::Parse {
+                line: 0,
+                column: 0,
+                message: "version must be a non-negative integer".to_string(),
+            })
+        }
+    };
+    if version > CONFIG_VERSION as u64 {
+        return Err(ConfigError::UnsupportedVersion(version));
+    }
+
+    let version = version as u32;
+    for migration_version in version..CONFIG_VERSION {
+        MIGRATIONS[migration_version as usize](config_object);
+        config_object.insert("version".to_string(), Value::from(migration_version + 1));
+    }
+
+    return Ok(version);
+}
+
+fn migrate_0_to_1(_config: &mut Map<String, Value>) {
+    // nothing else changed, only the version field was added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_current_version() {
+        let parsed = parse_config(
+            r#"{"version": 1, "hidden_apps": ["firefox.desktop"]}"#,
+            ConfigFormat::Json,
+        )
+        .unwrap();
+        assert_eq!(parsed.version, 1);
+        assert_eq!(parsed.config.get_hidden_apps(), &vec!["firefox.desktop"]);
+    }
+
+    #[test]
+    fn test_migrate_unversioned() {
+        let parsed =
+            parse_config(r#"{"hidden_apps": ["firefox.desktop"]}"#, ConfigFormat::Json).unwrap();
+        assert_eq!(parsed.version, 0);
+        assert_eq!(parsed.config.get_hidden_apps(), &vec!["firefox.desktop"]);
+        assert_eq!(parsed.value["version"], CONFIG_VERSION);
+
+        let saved = serde_json::to_value(&parsed.config).unwrap();
+        assert_eq!(saved["version"], CONFIG_VERSION);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_config(
+                "{\n  \"hidden_apps\": [\"firefox.desktop\",]\n}",
+                ConfigFormat::Json
+            )
+            .unwrap_err(),
+            ConfigError::Parse {
+                line: 2,
+                column: 37,
+                message: "trailing comma".to_string(),
+            }
+        );
+
+        let error = parse_config(
+            "{\n  \"version\": 1,\n  \"hidden_apps\": \"firefox.desktop\"\n}",
+            ConfigFormat::Json,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 3, column 34: invalid type: string \"firefox.desktop\", expected a sequence"
+        );
+
+        // unversioned config is migrated first, error is still in the original text
+        let error = parse_config(
+            "{\n  \"hidden_apps\": \"firefox.desktop\"\n}",
+            ConfigFormat::Json,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 2, column 34: invalid type: string \"firefox.desktop\", expected a sequence"
+        );
+
+        assert_eq!(
+            parse_config(r#"{"version": 99}"#, ConfigFormat::Json).unwrap_err(),
+            ConfigError::UnsupportedVersion(99)
+        );
+    }
+}
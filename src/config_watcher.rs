@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{debug, info, warn};
+
+use crate::config_formats::CONFIG_FILENAMES;
+use crate::{paths, MessageToMain};
+
+// editors often save in several steps (e.g truncate, write, rename), reload only once
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(300);
+
+// Sends MessageToMain::ConfigChanged when the config file changes, until the watcher is dropped.
+// All config file names are watched, so creating e.g config.toml next to config.json switches
+// to it right away.
+// The whole config directory is watched, as editors often replace the file instead of
+// writing to it.
+// System config files (see config_layers) are not watched, changes to them are used
+// after Browsers is restarted, or the next time the user's config file changes.
+pub fn watch_config(main_sender: Sender<MessageToMain>) -> Option<RecommendedWatcher> {
+    let config_root_dir = paths::get_config_root_dir();
+    let config_paths: Vec<PathBuf> = CONFIG_FILENAMES
+        .iter()
+        .map(|config_filename| config_root_dir.join(config_filename))
+        .collect();
+    let (change_sender, change_receiver) = mpsc::channel::<()>();
+
+    let event_handler = move |event_result: notify::Result<Event>| match event_result {
+        Ok(event) => {
+            if is_config_change(&event, config_paths.as_slice()) {
+                change_sender.send(()).ok();
+            }
+        }
+        Err(error) => warn!("Error while watching config: {}", error),
+    };
+    let watcher_result = notify::recommended_watcher(event_handler);
+    if watcher_result.is_err() {
+        warn!("Could not watch config: {}", watcher_result.unwrap_err());
+        return None;
+    }
+    let mut watcher = watcher_result.unwrap();
+
+    let watch_result = watcher.watch(config_root_dir.as_path(), RecursiveMode::NonRecursive);
+    if watch_result.is_err() {
+        warn!(
+            "Could not watch {}: {}",
+            config_root_dir.display(),
+            watch_result.unwrap_err()
+        );
+        return None;
+    }
+    info!("Watching {} for changes", config_root_dir.display());
+
+    thread::spawn(move || {
+        while change_receiver.recv().is_ok() {
+            // wait until the file stops changing
+            while change_receiver.recv_timeout(DEBOUNCE_DURATION).is_ok() {}
+
+            debug!("Config changed");
+            if main_sender.send(MessageToMain::ConfigChanged).is_err() {
+                break;
+            }
+        }
+    });
+
+    return Some(watcher);
+}
+
+// removing config.json is not a change, the config in use is kept until a config file is written
+fn is_config_change(event: &Event, config_paths: &[PathBuf]) -> bool {
+    let is_write = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+    return is_write && event.paths.iter().any(|path| config_paths.contains(path));
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, CreateKind, ModifyKind, RemoveKind, RenameMode};
+
+    use super::*;
+
+    #[test]
+    fn test_is_config_change() {
+        let config_json_path = PathBuf::from("/home/x/.config/software.Browsers/config.json");
+        let config_toml_path = PathBuf::from("/home/x/.config/software.Browsers/config.toml");
+        let other_path = PathBuf::from("/home/x/.config/software.Browsers/config.json.swp");
+        let config_paths = vec![config_toml_path.clone(), config_json_path.clone()];
+
+        let modified =
+            Event::new(EventKind::Modify(ModifyKind::Any)).add_path(config_json_path.clone());
+        assert_eq!(is_config_change(&modified, config_paths.as_slice()), true);
+
+        // written to a temporary file, then renamed over config.json
+        let renamed = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(other_path.clone())
+            .add_path(config_json_path.clone());
+        assert_eq!(is_config_change(&renamed, config_paths.as_slice()), true);
+
+        let created = Event::new(EventKind::Create(CreateKind::File)).add_path(other_path.clone());
+        assert_eq!(is_config_change(&created, config_paths.as_slice()), false);
+
+        let removed =
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(config_json_path.clone());
+        assert_eq!(is_config_change(&removed, config_paths.as_slice()), false);
+
+        let read =
+            Event::new(EventKind::Access(AccessKind::Any)).add_path(config_json_path.clone());
+        assert_eq!(is_config_change(&read, config_paths.as_slice()), false);
+
+        let toml_created =
+            Event::new(EventKind::Create(CreateKind::File)).add_path(config_toml_path.clone());
+        assert_eq!(is_config_change(&toml_created, config_paths.as_slice()), true);
+    }
+}
@@ -8,8 +8,8 @@ use std::sync::Arc;
 use druid::commands::{CONFIGURE_WINDOW_SIZE_AND_POSITION, QUIT_APP, SHOW_WINDOW};
 use druid::piet::{InterpolationMode, TextStorage};
 use druid::widget::{
-    Container, Controller, ControllerHost, CrossAxisAlignment, Either, Flex, Image, Label,
-    LineBreaking, List, ZStack,
+    Container, Controller, ControllerHost, CrossAxisAlignment, Either, EnvScope, Flex, Image,
+    Label, LineBreaking, List, ZStack,
 };
 use druid::{
     image, Application, BoxConstraints, Code, FontDescriptor, FontFamily, FontWeight, LayoutCtx,
@@ -19,13 +19,14 @@ use druid::{
 };
 use druid::{
     AppDelegate, AppLauncher, Color, Command, Data, DelegateCtx, Env, Event, EventCtx, Handled,
-    ImageBuf, KbKey, KeyEvent, Lens, PaintCtx, Point, RenderContext, Selector, Size, Target,
+    ImageBuf, KbKey, Key, KeyEvent, Lens, PaintCtx, Point, RenderContext, Selector, Size, Target,
     Widget, WidgetExt, WindowDesc, WindowId,
 };
 use image::io::Reader as ImageReader;
 use tracing::{debug, info, instrument};
 use url::Url;
 
+use crate::opening_rules::{self, RememberScope};
 use crate::url_rule::UrlGlobMatcher;
 use crate::utils::UIConfig;
 use crate::{paths, CommonBrowserProfile, MessageToMain};
@@ -38,6 +39,9 @@ const PADDING_Y: f64 = 10.0;
 const ITEM_WIDTH: f64 = 210.0;
 const ITEM_HEIGHT: f64 = 32.0;
 
+// from UIState, so it can change when config is reloaded
+const SHOW_HOTKEYS: Key<bool> = Key::new("browsers.show_hotkeys");
+
 pub struct UI {
     localizations_basedir: PathBuf,
     main_sender: Sender<MessageToMain>,
@@ -168,6 +172,8 @@ impl UI {
             selected_browser: "".to_string(),
             focused_index: None,
             incognito_mode: false,
+            remember_scope: None,
+            show_hotkeys: self.show_hotkeys,
             browsers: self.ui_browsers.clone(),
             filtered_browsers: self.filtered_browsers.clone(),
             restorable_app_profiles: self.restorable_app_profiles.clone(),
@@ -266,12 +272,14 @@ impl UI {
 
         //LensWrap::new(self, then1);
 
-        let show_hotkeys = self.show_hotkeys;
-        let browsers_list =
-            List::new(move || create_browser(ImageBuf::empty(), ImageBuf::empty(), show_hotkeys))
-                .with_spacing(0.0)
-                .lens((UIState::incognito_mode, UIState::filtered_browsers))
-                .scroll();
+        let browsers_list = List::new(|| create_browser(ImageBuf::empty(), ImageBuf::empty()))
+            .with_spacing(0.0)
+            .lens((UIState::incognito_mode, UIState::filtered_browsers))
+            .scroll();
+        let browsers_list = EnvScope::new(
+            |env, data: &UIState| env.set(SHOW_HOTKEYS, data.show_hotkeys),
+            browsers_list,
+        );
 
         // viewport size is fixed, while scrollable are is full size
         let browsers_list = Container::new(browsers_list).expand_height();
@@ -296,6 +304,9 @@ pub struct UIState {
     selected_browser: String,
     focused_index: Option<usize>,
     incognito_mode: bool,
+    // creates a rule for the chosen profile, if set
+    remember_scope: Option<RememberScope>,
+    show_hotkeys: bool,
 
     browsers: Arc<Vec<UIBrowser>>,
 
@@ -319,7 +330,8 @@ impl FocusData for (bool, UIBrowser) {
 
 #[derive(Clone, Data, Lens)]
 pub struct UIBrowser {
-    // index in not-explicitly-hidden browsers list, used to send message to main event cycle
+    // index in not-explicitly-hidden browsers list, used to find the browser for ui commands
+    // (messages to main event cycle use unique_id instead, as the list there can change)
     // is not impacted by current url, i.e no filters apply
     browser_profile_index: usize,
     is_first: bool,
@@ -342,6 +354,10 @@ pub struct UIBrowser {
 }
 
 impl UIBrowser {
+    pub fn get_unique_id(&self) -> &str {
+        return self.unique_id.as_str();
+    }
+
     pub fn has_priority_ordering(&self) -> bool {
         return !self.restricted_url_matchers.is_empty();
     }
@@ -393,6 +409,8 @@ pub const RESTORE_HIDDEN_PROFILE: Selector<String> =
 
 pub const MOVE_PROFILE: Selector<(String, MoveTo)> = Selector::new("browsers.move_profile");
 
+pub const UI_CONFIG_RECEIVED: Selector<UIConfig> = Selector::new("browsers.ui_config_received");
+
 #[derive(Clone, Copy, Debug)]
 pub enum MoveTo {
     UP,
@@ -601,9 +619,28 @@ impl AppDelegate<UIState> for UIDelegate {
             Handled::Yes
         } else if cmd.is(OPEN_LINK_IN_BROWSER) {
             let profile_index = cmd.get_unchecked(OPEN_LINK_IN_BROWSER);
+            let browser_maybe = data
+                .browsers
+                .iter()
+                .find(|b| b.browser_profile_index == *profile_index);
+            if browser_maybe.is_none() {
+                return Handled::Yes;
+            }
+            let profile_id = browser_maybe.unwrap().unique_id.clone();
+
+            if let Some(remember_scope) = data.remember_scope {
+                self.main_sender
+                    .send(MessageToMain::RememberChoice(
+                        profile_id.clone(),
+                        data.incognito_mode,
+                        data.url.to_string(),
+                        remember_scope,
+                    ))
+                    .ok();
+            }
             self.main_sender
                 .send(MessageToMain::OpenLink(
-                    *profile_index,
+                    profile_id,
                     data.incognito_mode,
                     data.url.to_string(),
                 ))
@@ -617,6 +654,11 @@ impl AppDelegate<UIState> for UIDelegate {
         } else if cmd.is(REFRESH) {
             self.main_sender.send(MessageToMain::Refresh).ok();
             Handled::Yes
+        } else if cmd.is(UI_CONFIG_RECEIVED) {
+            let ui_config = cmd.get_unchecked(UI_CONFIG_RECEIVED);
+            data.show_hotkeys = ui_config.show_hotkeys;
+            self.quit_on_lost_focus = ui_config.quit_on_lost_focus;
+            Handled::Yes
         } else if cmd.is(NEW_BROWSERS_RECEIVED) {
             let ui_browsers = cmd.get_unchecked(NEW_BROWSERS_RECEIVED).clone();
             // let old_v = std::mem::replace(&mut data.browsers, Arc::new(ui_browsers));
@@ -908,11 +950,47 @@ fn make_hidden_apps_menu(hidden_profiles: Arc<Vec<UIBrowser>>) -> Menu<UIState>
     return submenu_hidden_apps;
 }
 
+// checkbox-like choice of whether and how the next picked profile is remembered as a rule
+fn make_remember_choice_menu() -> Menu<UIState> {
+    let scopes = [
+        ("Don't Remember", None),
+        ("Remember for This Host", Some(RememberScope::Host)),
+        (
+            "Remember for This Domain",
+            Some(RememberScope::RegistrableDomain),
+        ),
+        (
+            "Remember for This Host and Path",
+            Some(RememberScope::HostAndPathPrefix),
+        ),
+    ];
+
+    let mut submenu_remember_choice = Menu::new(LocalizedString::new("Remember Choice"));
+    for (title, scope) in scopes {
+        submenu_remember_choice = submenu_remember_choice.entry(
+            MenuItem::new(LocalizedString::new(title))
+                .on_activate(move |_ctx, data: &mut UIState, _env| {
+                    data.remember_scope = scope;
+                })
+                .selected_if(move |data: &UIState, _env| data.remember_scope == scope)
+                // e.g no domain for ip addresses
+                .enabled_if(move |data: &UIState, _env| {
+                    scope.map_or(true, |scope| {
+                        opening_rules::can_remember(data.url.as_str(), scope)
+                    })
+                }),
+        );
+    }
+
+    return submenu_remember_choice;
+}
+
 fn make_options_menu(
     show_set_as_default: bool,
     hidden_browsers: Arc<Vec<UIBrowser>>,
 ) -> Menu<UIState> {
     let submenu_hidden_apps = make_hidden_apps_menu(hidden_browsers);
+    let submenu_remember_choice = make_remember_choice_menu();
 
     let mut menu = Menu::empty();
 
@@ -933,6 +1011,7 @@ fn make_options_menu(
     }
 
     menu = menu
+        .entry(submenu_remember_choice)
         .entry(submenu_hidden_apps)
         .entry(MenuItem::new(LocalizedString::new("About")).on_activate(
             |ctx, _data: &mut UIState, _env| {
@@ -1115,7 +1194,6 @@ fn create_browser_label() -> Label<(bool, UIBrowser)> {
 fn create_browser(
     app_icon_buf: ImageBuf,
     profile_img_buf: ImageBuf,
-    show_hotkeys: bool,
 ) -> impl Widget<(bool, UIBrowser)> {
     let icon_size = get_icon_size();
     let icon_padding = get_icon_padding();
@@ -1184,8 +1262,8 @@ fn create_browser(
         .with_size(text_size);
 
     let hotkey_label = Either::new(
-        move |(_incognito_mode, item): &(bool, UIBrowser), _env| {
-            show_hotkeys && item.filtered_index < 9
+        |(_incognito_mode, item): &(bool, UIBrowser), env| {
+            env.get(SHOW_HOTKEYS) && item.filtered_index < 9
         },
         {
             let hotkey_label =
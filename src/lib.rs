@@ -1,7 +1,6 @@
 use std::borrow::Borrow;
 use std::fmt::Debug;
 use std::process::{exit, Command};
-use std::str::FromStr;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
@@ -9,22 +8,35 @@ use std::thread;
 use druid::{ExtEventSink, Target, UrlOpenInfo};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, instrument, warn};
-use url::Url;
 
 use gui::ui;
 
 use crate::browser_repository::{SupportedApp, SupportedAppRepository};
+use crate::config_formats::ConfigFormat;
 use crate::gui::ui::MoveTo;
-use crate::gui::ui::UI;
+use crate::gui::ui::{UIBrowser, UI};
+use crate::opening_rules::{
+    create_remembered_rule, get_rule_for_source_app_and_url, OpeningRule, RememberScope,
+    RulesValidationReport,
+};
+use crate::profile_ids::IdAliases;
+use crate::rule_actions::RuleAction;
+use crate::rule_conditions::{LazySourceApp, SystemClock, SystemEnvironment};
 use crate::url_rule::UrlGlobMatcher;
-use crate::utils::{OSAppFinder, ProfileAndOptions};
+use crate::utils::{Config, OSAppFinder, ProfileAndOptions};
 
 mod gui;
 
+pub mod cli;
 pub mod paths;
 pub mod utils;
 
 mod browser_repository;
+mod config_file;
+mod config_formats;
+mod config_layers;
+mod config_migrations;
+mod config_watcher;
 
 #[cfg(target_os = "macos")]
 mod macos_utils;
@@ -35,6 +47,12 @@ mod macos;
 #[cfg(target_os = "linux")]
 mod linux_utils;
 
+#[cfg(target_os = "linux")]
+mod linux_mimeapps_list;
+
+#[cfg(target_os = "linux")]
+mod linux_source_app;
+
 #[cfg(target_os = "windows")]
 mod windows_utils;
 
@@ -43,6 +61,11 @@ pub mod communicate;
 mod chromium_profiles_parser;
 mod firefox_profiles_parser;
 mod slack_profiles_parser;
+mod opening_rules;
+mod profile_ids;
+mod rule_actions;
+mod rule_conditions;
+mod settings_bundle;
 mod slack_url_parser;
 mod url_rule;
 
@@ -53,12 +76,17 @@ pub struct GenericApp {
 }
 
 impl GenericApp {
-    fn new(installed_browser: &InstalledBrowser, app_repository: &SupportedAppRepository) -> Self {
+    fn new(
+        installed_browser: &InstalledBrowser,
+        app_id: String,
+        app_repository: &SupportedAppRepository,
+    ) -> Self {
         let supported_app = app_repository.get_or_generate(
             installed_browser.bundle.as_str(),
             &installed_browser.restricted_domains,
         );
         let app = BrowserCommon {
+            app_id: app_id,
             supported_app: supported_app,
             command: installed_browser.command.clone(),
             executable_path: installed_browser.executable_path.to_string(),
@@ -85,6 +113,7 @@ impl GenericApp {
 
 #[derive(Clone)]
 pub struct BrowserCommon {
+    app_id: String,
     command: Vec<String>,
     executable_path: String,
     display_name: String,
@@ -95,6 +124,11 @@ pub struct BrowserCommon {
 impl BrowserCommon {
     // used in configuration file to uniquely identify this app
     fn get_unique_app_id(&self) -> String {
+        return self.app_id.to_string();
+    }
+
+    // what was used as the unique app id before, see profile_ids
+    fn get_legacy_app_id(&self) -> String {
         return self.executable_path.to_string();
     }
 
@@ -219,13 +253,14 @@ pub struct CommonBrowserProfile {
     profile_cli_container_name: Option<String>,
     profile_name: String,
     profile_icon: Option<String>,
+    profile_restricted_url_patterns: Vec<String>,
     profile_restricted_url_matchers: Vec<UrlGlobMatcher>,
     app: Arc<BrowserCommon>,
 }
 
 impl CommonBrowserProfile {
     fn new(installed_browser_profile: &InstalledBrowserProfile, app: Arc<BrowserCommon>) -> Self {
-        let profile_restricted_url_matchers = Self::generate_restricted_hostname_matchers(
+        let profile_restricted_url_matchers = url_rule::to_restricted_url_matchers(
             &installed_browser_profile.profile_restricted_url_patterns,
         );
 
@@ -239,29 +274,25 @@ impl CommonBrowserProfile {
                 .profile_icon
                 .as_ref()
                 .map(|path| path.clone()),
+            profile_restricted_url_patterns: installed_browser_profile
+                .profile_restricted_url_patterns
+                .clone(),
             profile_restricted_url_matchers: profile_restricted_url_matchers,
             app: app,
         }
     }
 
-    fn generate_restricted_hostname_matchers(
-        restricted_url_patterns: &Vec<String>,
-    ) -> Vec<UrlGlobMatcher> {
-        let restricted_hostname_matchers: Vec<UrlGlobMatcher> = restricted_url_patterns
-            .iter()
-            .map(|url_pattern| {
-                let url_matcher = url_rule::to_url_matcher(url_pattern.as_str());
-                let glob_matcher = url_matcher.to_glob_matcher();
-                glob_matcher
-            })
-            .collect();
+    // used in configuration file to uniquely identify this app+profile+container
+    fn get_unique_id(&self) -> String {
+        return self.get_id_for_app(self.get_unique_app_id());
+    }
 
-        return restricted_hostname_matchers;
+    // what was used as the unique id before, see profile_ids
+    fn get_legacy_unique_id(&self) -> String {
+        return self.get_id_for_app(self.get_browser_common().get_legacy_app_id());
     }
 
-    // used in configuration file to uniquely identify this app+profile+container
-    fn get_unique_id(&self) -> String {
-        let app_id = self.get_unique_app_id();
+    fn get_id_for_app(&self, app_id: String) -> String {
         let app_and_profile = app_id + "#" + self.profile_cli_arg_value.as_str();
 
         if let Some(ref profile_cli_container_name) = self.profile_cli_container_name {
@@ -295,6 +326,16 @@ impl CommonBrowserProfile {
         };
     }
 
+    fn get_restricted_url_patterns(&self) -> &Vec<String> {
+        return if !&self.profile_restricted_url_patterns.is_empty() {
+            &self.profile_restricted_url_patterns
+        } else {
+            self.get_browser_common()
+                .supported_app
+                .get_restricted_url_patterns()
+        };
+    }
+
     fn get_browser_name(&self) -> &str {
         return self.get_browser_common().get_display_name();
     }
@@ -343,7 +384,7 @@ pub struct InstalledBrowser {
 
     display_name: String,
 
-    // macOS only
+    // bundle id in macOS, desktop id in Linux, app name in Windows
     bundle: String,
 
     user_dir: String,
@@ -374,14 +415,6 @@ pub enum ProfileIcon {
     Name { name: String },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct OpeningRule {
-    source_app: Option<String>,
-    url_pattern: Option<String>,
-    profile: String,
-    incognito: bool,
-}
-
 #[instrument(skip_all)]
 fn generate_all_browser_profiles(
     app_finder: &OSAppFinder,
@@ -391,32 +424,49 @@ fn generate_all_browser_profiles(
     Option<ProfileAndOptions>,
     Vec<CommonBrowserProfile>,
     Vec<CommonBrowserProfile>,
+    RulesValidationReport,
 ) {
-    let installed_browsers = app_finder.get_installed_browsers_cached(force_reload);
     let config = app_finder.get_installed_browsers_config();
+    return generate_browser_profiles_for_config(app_finder, config, force_reload);
+}
+
+fn generate_browser_profiles_for_config(
+    app_finder: &OSAppFinder,
+    mut config: Config,
+    force_reload: bool,
+) -> (
+    Vec<OpeningRule>,
+    Option<ProfileAndOptions>,
+    Vec<CommonBrowserProfile>,
+    Vec<CommonBrowserProfile>,
+    RulesValidationReport,
+) {
+    let installed_browsers = app_finder.get_installed_browsers_cached(force_reload);
+    let app_ids = profile_ids::stable_app_ids(&installed_browsers);
+    let apps: Vec<GenericApp> = installed_browsers
+        .iter()
+        .zip(app_ids)
+        .map(|(installed_browser, app_id)| {
+            GenericApp::new(installed_browser, app_id, app_finder.get_app_repository())
+        })
+        .collect();
+
+    migrate_legacy_ids(app_finder, &mut config, &apps);
     let hidden_apps = config.get_hidden_apps();
     let hidden_profiles = config.get_hidden_profiles();
 
     let config_rules = config.get_rules();
     let default_profile = config.get_default_profile();
-    let opening_rules = config_rules
-        .iter()
-        .map(|r| OpeningRule {
-            source_app: r.source_app.clone(),
-            url_pattern: r.url_pattern.clone(),
-            profile: r.profile.clone(),
-            incognito: r.incognito.clone(),
-        })
-        .collect();
+    let (opening_rules, mut rules_report) =
+        opening_rules::compile_rules(config_rules, config.get_rule_matching());
 
     let mut visible_browser_profiles: Vec<CommonBrowserProfile> = Vec::new();
     let mut hidden_browser_profiles: Vec<CommonBrowserProfile> = Vec::new();
     //let support_dir = macos_get_application_support_dir();
     debug!("Apps");
-    for installed_browser in installed_browsers {
+    for (installed_browser, app) in installed_browsers.iter().zip(apps.iter()) {
         debug!("App: {:?}", installed_browser.bundle);
         debug!("  Path: {:?}", installed_browser.executable_path);
-        let app = GenericApp::new(&installed_browser, app_finder.get_app_repository());
 
         for p in app.get_profiles() {
             let app_id = p.get_unique_app_id();
@@ -447,14 +497,78 @@ fn generate_all_browser_profiles(
     let profile_order = config.get_profile_order();
     sort_browser_profiles(&mut visible_browser_profiles, profile_order);
 
+    let existing_profile_ids: Vec<String> = visible_browser_profiles
+        .iter()
+        .chain(hidden_browser_profiles.iter())
+        .map(|p| p.get_unique_id())
+        .collect();
+    let missing_profiles =
+        opening_rules::find_missing_profiles(&opening_rules, existing_profile_ids.as_slice());
+    // other problems were logged when compiling rules
+    let missing_profiles_report = RulesValidationReport {
+        missing_profiles: missing_profiles.clone(),
+        ..RulesValidationReport::default()
+    };
+    for line in missing_profiles_report.summary_lines() {
+        warn!("{}", line);
+    }
+    rules_report.missing_profiles = missing_profiles;
+
     return (
         opening_rules,
         default_profile.clone(),
         visible_browser_profiles,
         hidden_browser_profiles,
+        rules_report,
     );
 }
 
+// replaces path based ids in config with stable ids, see profile_ids
+fn migrate_legacy_ids(app_finder: &OSAppFinder, config: &mut Config, apps: &[GenericApp]) {
+    let mut aliases = IdAliases::new();
+    for app in apps {
+        let legacy_app_id = app.app.get_legacy_app_id();
+        let app_id = app.app.get_unique_app_id();
+        if legacy_app_id != app_id {
+            aliases.insert(legacy_app_id, app_id);
+        }
+
+        for p in app.get_profiles() {
+            let legacy_id = p.get_legacy_unique_id();
+            let id = p.get_unique_id();
+            if legacy_id != id {
+                aliases.insert(legacy_id, id);
+            }
+        }
+    }
+
+    let renamed_count = config.migrate_legacy_ids(&aliases);
+    if renamed_count == 0 {
+        return;
+    }
+
+    info!("Replacing {} legacy ids in config", renamed_count);
+    // formats which can't be saved are migrated again every time they are read
+    let config_path = paths::get_config_file_path();
+    let format = ConfigFormat::from_path(config_path.as_path()).unwrap_or(ConfigFormat::Json);
+    if !format.can_save() {
+        return;
+    }
+    let update_result = app_finder.update_installed_browsers_config(|saved_config| {
+        // legacy ids can also be only in system config files, which are not changed
+        let saved_renamed_count = saved_config.migrate_legacy_ids(&aliases);
+        if saved_renamed_count > 0 {
+            // taken before the user's config file is changed for the first time
+            app_finder.backup_installed_browsers_config_once(
+                profile_ids::LEGACY_IDS_CONFIG_BACKUP_FILENAME,
+            );
+        }
+    });
+    if update_result.is_err() {
+        warn!("Could not save migrated config: {}", update_result.unwrap_err());
+    }
+}
+
 fn sort_browser_profiles(
     visible_browser_profiles: &mut Vec<CommonBrowserProfile>,
     profile_order: &Vec<String>,
@@ -472,57 +586,6 @@ fn sort_browser_profiles(
     visible_browser_profiles.sort_by_key(|b| !b.has_priority_ordering());
 }
 
-#[instrument(skip_all)]
-fn get_rule_for_source_app_and_url(
-    opening_rules: &Vec<OpeningRule>,
-    default_profile_maybe: Option<ProfileAndOptions>,
-    url: &str,
-    source_app_maybe: Option<String>,
-) -> Option<ProfileAndOptions> {
-    let url_result = Url::from_str(url);
-    if url_result.is_err() {
-        return None;
-    }
-    let given_url = url_result.unwrap();
-
-    for r in opening_rules {
-        let mut source_app_match = false;
-        let url_match = if let Some(ref url_pattern) = r.url_pattern {
-            let url_matches = url_rule::to_url_matcher(url_pattern.as_str())
-                .to_glob_matcher()
-                .url_matches(&given_url);
-
-            url_matches
-        } else {
-            true
-        };
-
-        if let Some(ref source_app) = r.source_app {
-            let source_app_rule = source_app.clone();
-            if let Some(ref source_app) = source_app_maybe {
-                let source_app = source_app.clone();
-                source_app_match = source_app_rule == source_app;
-            }
-        } else {
-            source_app_match = true;
-        }
-
-        if url_match && source_app_match {
-            let profile_and_options = ProfileAndOptions {
-                profile: r.profile.clone(),
-                incognito: r.incognito.clone(),
-            };
-            return Some(profile_and_options);
-        }
-    }
-
-    if default_profile_maybe.is_some() {
-        return default_profile_maybe;
-    }
-
-    return None;
-}
-
 fn get_browser_profile_by_id<'a>(
     visible_profiles: &'a [CommonBrowserProfile],
     hidden_profiles: &'a [CommonBrowserProfile],
@@ -542,12 +605,22 @@ fn get_browser_profile_by_id<'a>(
         return hidden_profile_maybe;
     }
 
+    // path based id from before stable ids, e.g in a script calling `browsers open`
+    let legacy_profile_maybe = visible_profiles
+        .iter()
+        .chain(hidden_profiles.iter())
+        .find(|p| p.get_legacy_unique_id() == unique_id);
+    if legacy_profile_maybe.is_some() {
+        return legacy_profile_maybe;
+    }
+
     return None;
 }
 
 #[instrument(skip_all)]
 pub fn basically_main(
     url: &str,
+    source_app_maybe: Option<String>,
     show_gui: bool,
     force_reload: bool,
     main_sender: Sender<MessageToMain>,
@@ -558,39 +631,68 @@ pub fn basically_main(
     let is_default = utils::is_default_web_browser();
     let show_set_as_default = !is_default;
 
-    let (opening_rules, default_profile, mut visible_browser_profiles, mut hidden_browser_profiles) =
-        generate_all_browser_profiles(&app_finder, force_reload);
+    app_finder.create_or_migrate_user_config();
+    let (config, config_error_maybe) = app_finder.get_installed_browsers_config_and_error();
+    if let Some(config_error) = config_error_maybe {
+        // just in case copy the config file for debugging
+        app_finder.backup_installed_browsers_config("config.corrupted.json");
+        utils::show_notification(
+            "Browsers could not read its config",
+            utils::describe_config_error(&config_error).as_str(),
+        );
+    }
 
+    let (
+        mut opening_rules,
+        mut default_profile,
+        mut visible_browser_profiles,
+        mut hidden_browser_profiles,
+        _,
+    ) = generate_browser_profiles_for_config(&app_finder, config.clone(), force_reload);
+
+    // --source-app wins over the detected one
+    let source_app = match source_app_maybe {
+        Some(source_app) => LazySourceApp::known(Some(source_app)),
+        None => LazySourceApp::detected_with(utils::detect_source_app),
+    };
     // TODO: url should not be considered here in case of macos
     //       and only the one in LinkOpenedFromBundle should be considered
-    let opening_profile_maybe =
-        get_rule_for_source_app_and_url(&opening_rules, default_profile.clone(), url, None);
-    if let Some(opening_profile_id) = opening_profile_maybe {
-        let profile_and_options = opening_profile_id.clone();
-        let profile_id = profile_and_options.profile;
-        let incognito = profile_and_options.incognito;
-
-        let profile_maybe = get_browser_profile_by_id(
+    let rule_action_maybe = get_rule_for_source_app_and_url(
+        &opening_rules,
+        default_profile.clone(),
+        url,
+        &source_app,
+        &SystemClock,
+        &SystemEnvironment,
+    );
+    let mut picker_profile_ids_maybe: Option<Vec<String>> = None;
+    if let Some(rule_action) = rule_action_maybe {
+        let action_outcome = perform_rule_action(
+            &rule_action,
+            url,
             visible_browser_profiles.as_slice(),
             hidden_browser_profiles.as_slice(),
-            profile_id.as_str(),
         );
-        if let Some(profile) = profile_maybe {
-            profile.open_link(url, incognito);
-            return;
+        match action_outcome {
+            ActionOutcome::Done => return,
+            ActionOutcome::ShowPicker(profile_ids_maybe) => {
+                picker_profile_ids_maybe = profile_ids_maybe;
+            }
         }
     }
 
     let localizations_basedir = paths::get_localizations_basedir();
 
-    let config = app_finder.get_installed_browsers_config();
     let ui_config = config.get_ui_config();
 
     let ui2 = UI::new(
         localizations_basedir,
         main_sender.clone(),
         url,
-        UI::real_to_ui_browsers(visible_browser_profiles.as_slice()),
+        picker_ui_browsers(
+            visible_browser_profiles.as_slice(),
+            picker_profile_ids_maybe.as_ref(),
+        ),
         UI::real_to_ui_browsers(hidden_browser_profiles.as_slice()),
         show_set_as_default,
         ui_config,
@@ -599,22 +701,83 @@ pub fn basically_main(
     let launcher = ui2.create_app_launcher();
     let ui_event_sink = launcher.get_external_handle();
 
+    // kept until the app exits
+    let _config_watcher_maybe = config_watcher::watch_config(main_sender.clone());
+
     thread::spawn(move || {
         for message in main_receiver.iter() {
             match message {
                 MessageToMain::Refresh => {
                     info!("refresh called");
-                    let (_, _, visible_browser_profiles, _) =
+                    let (_, _, visible_browser_profiles, _, _) =
                         generate_all_browser_profiles(&app_finder, true);
 
-                    let ui_browsers = UI::real_to_ui_browsers(&visible_browser_profiles);
+                    let ui_browsers = picker_ui_browsers(
+                        visible_browser_profiles.as_slice(),
+                        picker_profile_ids_maybe.as_ref(),
+                    );
+                    ui_event_sink
+                        .submit_command(ui::NEW_BROWSERS_RECEIVED, ui_browsers, Target::Global)
+                        .ok();
+                }
+                MessageToMain::ConfigChanged => {
+                    let config_result = app_finder.load_installed_browsers_config();
+                    if config_result.is_err() {
+                        // keep using the last config which was valid
+                        let error = config_result.unwrap_err();
+                        warn!("Not reloading config: {}", error);
+                        utils::show_notification(
+                            "Browsers did not reload its config",
+                            error.to_string().as_str(),
+                        );
+                        continue;
+                    }
+                    info!("Reloading config");
+                    let config = config_result.unwrap();
+                    let ui_config = config.get_ui_config().clone();
+
+                    (
+                        opening_rules,
+                        default_profile,
+                        visible_browser_profiles,
+                        hidden_browser_profiles,
+                        _,
+                    ) = generate_browser_profiles_for_config(&app_finder, config, false);
+
+                    // keeps the picker limited by a picker rule action
+                    let ui_browsers = picker_ui_browsers(
+                        visible_browser_profiles.as_slice(),
+                        picker_profile_ids_maybe.as_ref(),
+                    );
                     ui_event_sink
                         .submit_command(ui::NEW_BROWSERS_RECEIVED, ui_browsers, Target::Global)
                         .ok();
+
+                    let ui_hidden_browsers = UI::real_to_ui_browsers(&hidden_browser_profiles);
+                    ui_event_sink
+                        .submit_command(
+                            ui::NEW_HIDDEN_BROWSERS_RECEIVED,
+                            ui_hidden_browsers,
+                            Target::Global,
+                        )
+                        .ok();
+
+                    ui_event_sink
+                        .submit_command(ui::UI_CONFIG_RECEIVED, ui_config, Target::Global)
+                        .ok();
                 }
-                MessageToMain::OpenLink(profile_index, incognito_mode, url) => {
-                    let option = &visible_browser_profiles.get(profile_index);
-                    let profile = option.unwrap();
+                MessageToMain::OpenLink(profile_id, incognito_mode, url) => {
+                    let profile_maybe = get_browser_profile_by_id(
+                        visible_browser_profiles.as_slice(),
+                        hidden_browser_profiles.as_slice(),
+                        profile_id.as_str(),
+                    );
+                    if profile_maybe.is_none() {
+                        // e.g removed by a config reload while the picker was open
+                        warn!("Not opening link, profile {} doesn't exist", profile_id);
+                        continue;
+                    }
+                    let profile = profile_maybe.unwrap();
                     profile.open_link(url.as_str(), incognito_mode);
                     ui_event_sink
                         .submit_command(
@@ -624,6 +787,27 @@ pub fn basically_main(
                         )
                         .ok();
                 }
+                MessageToMain::RememberChoice(profile_id, incognito_mode, url, scope) => {
+                    let profile_maybe = get_browser_profile_by_id(
+                        visible_browser_profiles.as_slice(),
+                        hidden_browser_profiles.as_slice(),
+                        profile_id.as_str(),
+                    );
+                    if profile_maybe.is_none() {
+                        warn!("Not remembering choice, profile {} doesn't exist", profile_id);
+                        continue;
+                    }
+                    let profile = profile_maybe.unwrap();
+                    let profile_and_options = ProfileAndOptions {
+                        profile: profile.get_unique_id(),
+                        incognito: incognito_mode,
+                    };
+                    let new_opening_rules_maybe =
+                        remember_choice(&app_finder, url.as_str(), scope, &profile_and_options);
+                    if let Some(new_opening_rules) = new_opening_rules_maybe {
+                        opening_rules = new_opening_rules;
+                    }
+                }
                 MessageToMain::UrlOpenRequest(from_bundle_id, url) => {
                     let url_open_info = UrlOpenInfo {
                         url: url,
@@ -647,33 +831,50 @@ pub fn basically_main(
                         exit(0x0100);
                     }
                     debug!("url: {}", url);
-                    let opening_profile_id_maybe = get_rule_for_source_app_and_url(
+                    let rule_action_maybe = get_rule_for_source_app_and_url(
                         &opening_rules,
                         default_profile.clone(),
                         url.as_str(),
-                        Some(from_bundle_id.clone()),
+                        &LazySourceApp::known(Some(from_bundle_id.clone())),
+                        &SystemClock,
+                        &SystemEnvironment,
                     );
-                    if let Some(opening_profile_id) = opening_profile_id_maybe {
-                        let profile_and_options = opening_profile_id.clone();
-                        let profile_id = profile_and_options.profile;
-                        let incognito = profile_and_options.incognito;
-
-                        let profile_maybe = get_browser_profile_by_id(
+                    let mut new_picker_profile_ids_maybe: Option<Vec<String>> = None;
+                    if let Some(rule_action) = rule_action_maybe {
+                        let action_outcome = perform_rule_action(
+                            &rule_action,
+                            url.as_str(),
                             visible_browser_profiles.as_slice(),
                             hidden_browser_profiles.as_slice(),
-                            profile_id.as_str(),
                         );
-                        if let Some(profile) = profile_maybe {
-                            profile.open_link(url.as_str(), incognito);
-                            ui_event_sink
-                                .submit_command(
-                                    ui::OPEN_LINK_IN_BROWSER_COMPLETED,
-                                    "meh2".to_string(),
-                                    Target::Global,
-                                )
-                                .ok();
+                        match action_outcome {
+                            ActionOutcome::Done => {
+                                ui_event_sink
+                                    .submit_command(
+                                        ui::OPEN_LINK_IN_BROWSER_COMPLETED,
+                                        "meh2".to_string(),
+                                        Target::Global,
+                                    )
+                                    .ok();
+                            }
+                            ActionOutcome::ShowPicker(profile_ids_maybe) => {
+                                new_picker_profile_ids_maybe = profile_ids_maybe;
+                            }
                         }
                     }
+
+                    // the picker can still be limited for the previous link
+                    if new_picker_profile_ids_maybe.is_some() || picker_profile_ids_maybe.is_some()
+                    {
+                        picker_profile_ids_maybe = new_picker_profile_ids_maybe;
+                        let ui_browsers = picker_ui_browsers(
+                            visible_browser_profiles.as_slice(),
+                            picker_profile_ids_maybe.as_ref(),
+                        );
+                        ui_event_sink
+                            .submit_command(ui::NEW_BROWSERS_RECEIVED, ui_browsers, Target::Global)
+                            .ok();
+                    }
                 }
                 MessageToMain::SetBrowsersAsDefaultBrowser => {
                     utils::set_as_default_web_browser();
@@ -687,9 +888,12 @@ pub fn basically_main(
                         .map(|p| p.get_unique_id())
                         .collect();
 
-                    let mut config = app_finder.get_installed_browsers_config();
-                    config.hide_all_profiles(&to_hide);
-                    app_finder.save_installed_browsers_config(&config);
+                    let update_result = app_finder.update_installed_browsers_config(|config| {
+                        config.hide_all_profiles(&to_hide)
+                    });
+                    if update_result.is_err() {
+                        warn!("Could not save hidden profiles: {}", update_result.unwrap_err());
+                    }
 
                     visible_browser_profiles.retain(|visible_profile| {
                         let delete = visible_profile.get_unique_app_id() == app_id;
@@ -716,9 +920,11 @@ pub fn basically_main(
                 MessageToMain::HideAppProfile(unique_id) => {
                     info!("Hiding profile {}", unique_id);
 
-                    let mut config = app_finder.get_installed_browsers_config();
-                    config.hide_profile(unique_id.as_str());
-                    app_finder.save_installed_browsers_config(&config);
+                    let update_result = app_finder
+                        .update_installed_browsers_config(|config| config.hide_profile(&unique_id));
+                    if update_result.is_err() {
+                        warn!("Could not save hidden profile: {}", update_result.unwrap_err());
+                    }
 
                     let visible_profile_index_maybe = visible_browser_profiles
                         .iter()
@@ -745,13 +951,31 @@ pub fn basically_main(
                 }
                 MessageToMain::RestoreAppProfile(unique_id) => {
                     info!("Restoring profile {}", unique_id);
+                    if app_finder.is_hidden_by_system_config(unique_id.as_str()) {
+                        info!(
+                            "Not restoring profile {}, it is hidden by system config",
+                            unique_id
+                        );
+                        utils::show_notification(
+                            "Browsers can't show this profile",
+                            "It is hidden by the system config of this computer",
+                        );
+                        continue;
+                    }
                     // will add to the end of visible profiles
 
-                    let mut config = app_finder.get_installed_browsers_config();
-                    config.restore_profile(unique_id.as_str());
-                    app_finder.save_installed_browsers_config(&config);
-
-                    let profile_order = config.get_profile_order();
+                    let update_result = app_finder.update_installed_browsers_config(|config| {
+                        config.restore_profile(unique_id.as_str());
+                        return config.get_profile_order().clone();
+                    });
+                    let profile_order = match update_result {
+                        Ok(profile_order) => profile_order,
+                        Err(error) => {
+                            // restored profile is still shown, at the end
+                            warn!("Could not save restored profile: {}", error);
+                            vec![]
+                        }
+                    };
 
                     let hidden_profile_index_maybe = hidden_browser_profiles
                         .iter()
@@ -760,7 +984,7 @@ pub fn basically_main(
                         let hidden_profile = hidden_browser_profiles.remove(hidden_profile_index);
                         visible_browser_profiles.push(hidden_profile);
 
-                        sort_browser_profiles(&mut visible_browser_profiles, profile_order);
+                        sort_browser_profiles(&mut visible_browser_profiles, &profile_order);
 
                         let ui_browsers = UI::real_to_ui_browsers(&visible_browser_profiles);
                         ui_event_sink
@@ -875,15 +1099,166 @@ fn move_app_profile(
         .map(|p| p.get_unique_id())
         .collect();
 
-    let mut config = app_finder.get_installed_browsers_config();
-    config.set_profile_order(&profile_ids_sorted);
-    app_finder.save_installed_browsers_config(&config);
+    let update_result = app_finder
+        .update_installed_browsers_config(|config| config.set_profile_order(&profile_ids_sorted));
+    if update_result.is_err() {
+        warn!("Could not save profile order: {}", update_result.unwrap_err());
+    }
+}
+
+// What is left to do after the action of a rule
+enum ActionOutcome {
+    Done,
+    // picker with only the given profiles (or all if None)
+    ShowPicker(Option<Vec<String>>),
+}
+
+fn perform_rule_action(
+    rule_action: &RuleAction,
+    url: &str,
+    visible_browser_profiles: &[CommonBrowserProfile],
+    hidden_browser_profiles: &[CommonBrowserProfile],
+) -> ActionOutcome {
+    info!("Performing {} action", rule_action.name());
+
+    return match rule_action {
+        RuleAction::OpenProfile { profile, incognito } => {
+            // first existing profile of the candidates
+            for profile_id in profile.to_vec() {
+                let profile_maybe = get_browser_profile_by_id(
+                    visible_browser_profiles,
+                    hidden_browser_profiles,
+                    profile_id.as_str(),
+                );
+                if let Some(profile) = profile_maybe {
+                    profile.open_link(url, *incognito);
+                    return ActionOutcome::Done;
+                }
+                warn!("Could not find profile {}, trying the next one", profile_id);
+            }
+            warn!("Could not find any of profiles {}, showing picker", profile);
+            ActionOutcome::ShowPicker(None)
+        }
+        RuleAction::OpenProfiles { profiles } => {
+            let mut opened_count = 0;
+            for profile_and_options in profiles {
+                let profile_maybe = get_browser_profile_by_id(
+                    visible_browser_profiles,
+                    hidden_browser_profiles,
+                    profile_and_options.profile.as_str(),
+                );
+                if let Some(profile) = profile_maybe {
+                    profile.open_link(url, profile_and_options.incognito);
+                    opened_count += 1;
+                } else {
+                    warn!("Could not find profile {}", profile_and_options.profile);
+                }
+            }
+            if opened_count > 0 {
+                ActionOutcome::Done
+            } else {
+                ActionOutcome::ShowPicker(None)
+            }
+        }
+        RuleAction::Picker { profiles } => ActionOutcome::ShowPicker(Some(profiles.clone())),
+        RuleAction::CopyToClipboard => {
+            if !utils::copy_to_clipboard(url) {
+                warn!("Could not copy url to clipboard");
+            }
+            ActionOutcome::Done
+        }
+        RuleAction::Block { message } => {
+            info!("Blocked {}", url);
+            let body = message.clone().unwrap_or(url.to_string());
+            utils::show_notification("Link blocked", body.as_str());
+            ActionOutcome::Done
+        }
+        RuleAction::RunCommand { command } => {
+            let command_with_url = rule_actions::command_with_url(command, url);
+            let spawn_result = Command::new(command_with_url[0].as_str())
+                .args(&command_with_url[1..])
+                .spawn();
+            if spawn_result.is_err() {
+                warn!(
+                    "Could not run {}: {}",
+                    command_with_url[0],
+                    spawn_result.unwrap_err()
+                );
+            }
+            ActionOutcome::Done
+        }
+    };
+}
+
+// all visible profiles, or only the given ones (in the order of visible profiles)
+fn picker_ui_browsers(
+    visible_browser_profiles: &[CommonBrowserProfile],
+    profile_ids_maybe: Option<&Vec<String>>,
+) -> Vec<UIBrowser> {
+    let ui_browsers = UI::real_to_ui_browsers(visible_browser_profiles);
+    return match profile_ids_maybe {
+        Some(profile_ids) => ui_browsers
+            .into_iter()
+            .filter(|ui_browser| {
+                profile_ids
+                    .iter()
+                    .any(|id| id == ui_browser.get_unique_id())
+            })
+            .collect(),
+        None => ui_browsers,
+    };
+}
+
+// Saves a rule for the choice made in the picker, returns the recompiled rules if it was saved
+fn remember_choice(
+    app_finder: &OSAppFinder,
+    url: &str,
+    scope: RememberScope,
+    profile_and_options: &ProfileAndOptions,
+) -> Option<Vec<OpeningRule>> {
+    // rules from system config files count as well, a duplicate of them would never be used
+    let merged_config = app_finder.get_installed_browsers_config();
+    let update_result = app_finder.update_installed_browsers_config(|config| {
+        let rule_result =
+            create_remembered_rule(merged_config.get_rules(), url, scope, profile_and_options);
+        if rule_result.is_err() {
+            warn!("Not remembering choice: {}", rule_result.unwrap_err());
+            return false;
+        }
+        let rule = rule_result.unwrap();
+        info!(
+            "Remembering {} for {}",
+            rule.profile,
+            rule.url_pattern.clone().unwrap_or_default()
+        );
+        config.add_rule(rule);
+        return true;
+    });
+    if update_result.is_err() {
+        warn!(
+            "Could not save remembered choice: {}",
+            update_result.unwrap_err()
+        );
+        return None;
+    }
+    if !update_result.unwrap() {
+        return None;
+    }
+
+    // compiled from all config files, as rules from system config files still apply
+    let config = app_finder.get_installed_browsers_config();
+    let (opening_rules, _) =
+        opening_rules::compile_rules(config.get_rules(), config.get_rule_matching());
+    return Some(opening_rules);
 }
 
 #[derive(Debug)]
 pub enum MessageToMain {
     Refresh,
-    OpenLink(usize, bool, String),
+    ConfigChanged,
+    // unique id of the profile, as the ui can still show profiles from before a reload
+    OpenLink(String, bool, String),
+    RememberChoice(String, bool, String, RememberScope),
     UrlOpenRequest(String, String), // almost as LinkOpenedFromBundle, but triggers gui, not from gui
     LinkOpenedFromBundle(String, String),
     SetBrowsersAsDefaultBrowser,
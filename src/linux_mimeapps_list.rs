@@ -0,0 +1,248 @@
+// Minimal reader/writer for mimeapps.list files
+// see https://specifications.freedesktop.org/mime-apps-spec/latest/
+//
+// Keeps all lines it doesn't touch (comments, other groups, other mime types) as they were,
+// so that we can safely modify a file which is shared with other applications.
+
+const DEFAULT_APPLICATIONS_GROUP: &'static str = "Default Applications";
+const ADDED_ASSOCIATIONS_GROUP: &'static str = "Added Associations";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MimeAppsList {
+    lines: Vec<String>,
+}
+
+impl MimeAppsList {
+    pub fn parse(content: &str) -> Self {
+        let lines: Vec<String> = content.lines().map(|line| line.to_string()).collect();
+        return Self { lines: lines };
+    }
+
+    pub fn to_file_content(&self) -> String {
+        let mut content = self.lines.join("\n");
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        return content;
+    }
+
+    // returns the first desktop id from [Default Applications] for mime type
+    pub fn get_default_application(&self, mime_type: &str) -> Option<String> {
+        let line_index_maybe = self.find_key_line_index(DEFAULT_APPLICATIONS_GROUP, mime_type);
+        let line_index = line_index_maybe?;
+
+        let desktop_ids = parse_value(self.lines[line_index].as_str());
+        return desktop_ids.first().cloned();
+    }
+
+    pub fn set_default_application(&mut self, mime_type: &str, desktop_id: &str) {
+        self.set_value(
+            DEFAULT_APPLICATIONS_GROUP,
+            mime_type,
+            vec![desktop_id.to_string()],
+        );
+
+        // also put it first in [Added Associations], which is what xdg-mime does as well,
+        // because some desktop environments only look at associations
+        let mut associations = self
+            .find_key_line_index(ADDED_ASSOCIATIONS_GROUP, mime_type)
+            .map(|index| parse_value(self.lines[index].as_str()))
+            .unwrap_or_default();
+        associations.retain(|id| id != desktop_id);
+        associations.insert(0, desktop_id.to_string());
+        self.set_value(ADDED_ASSOCIATIONS_GROUP, mime_type, associations);
+    }
+
+    pub fn remove_default_application(&mut self, mime_type: &str) {
+        let line_index_maybe = self.find_key_line_index(DEFAULT_APPLICATIONS_GROUP, mime_type);
+        if let Some(line_index) = line_index_maybe {
+            self.lines.remove(line_index);
+        }
+    }
+
+    // removes desktop id from [Added Associations], and the whole line if it was the only one
+    pub fn remove_association(&mut self, mime_type: &str, desktop_id: &str) {
+        let line_index_maybe = self.find_key_line_index(ADDED_ASSOCIATIONS_GROUP, mime_type);
+        if line_index_maybe.is_none() {
+            return;
+        }
+        let line_index = line_index_maybe.unwrap();
+
+        let mut associations = parse_value(self.lines[line_index].as_str());
+        associations.retain(|id| id != desktop_id);
+        if associations.is_empty() {
+            self.lines.remove(line_index);
+        } else {
+            self.set_value(ADDED_ASSOCIATIONS_GROUP, mime_type, associations);
+        }
+    }
+
+    fn set_value(&mut self, group: &str, key: &str, values: Vec<String>) {
+        let mut value = values.join(";");
+        value.push(';');
+        let new_line = format!("{}={}", key, value);
+
+        if let Some(line_index) = self.find_key_line_index(group, key) {
+            self.lines[line_index] = new_line;
+            return;
+        }
+
+        let group_range_maybe = self.find_group_range(group);
+        if let Some((_, group_end_index)) = group_range_maybe {
+            // insert after last non-empty line of the group
+            let mut insert_index = group_end_index;
+            while insert_index > 0 && self.lines[insert_index - 1].trim().is_empty() {
+                insert_index -= 1;
+            }
+            self.lines.insert(insert_index, new_line);
+            return;
+        }
+
+        if !self.lines.is_empty() && !self.lines.last().unwrap().trim().is_empty() {
+            self.lines.push("".to_string());
+        }
+        self.lines.push(format!("[{}]", group));
+        self.lines.push(new_line);
+    }
+
+    // returns (index of group header, index after last line of group)
+    fn find_group_range(&self, group: &str) -> Option<(usize, usize)> {
+        let header = format!("[{}]", group);
+        let start_index = self
+            .lines
+            .iter()
+            .position(|line| line.trim() == header.as_str())?;
+
+        let end_index = self.lines[start_index + 1..]
+            .iter()
+            .position(|line| line.trim().starts_with('['))
+            .map(|i| start_index + 1 + i)
+            .unwrap_or(self.lines.len());
+
+        return Some((start_index, end_index));
+    }
+
+    fn find_key_line_index(&self, group: &str, key: &str) -> Option<usize> {
+        let (start_index, end_index) = self.find_group_range(group)?;
+
+        return (start_index + 1..end_index).find(|i| {
+            let line = self.lines[*i].as_str();
+            let (line_key, _) = line.split_once('=').unwrap_or(("", ""));
+            line_key.trim() == key
+        });
+    }
+}
+
+// "firefox.desktop;chromium.desktop;" -> ["firefox.desktop", "chromium.desktop"]
+fn parse_value(line: &str) -> Vec<String> {
+    let (_, value) = line.split_once('=').unwrap_or(("", ""));
+    return value
+        .split(';')
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_default_application() {
+        let mimeapps_list = MimeAppsList::parse(
+            "[Added Associations]\n\
+             x-scheme-handler/https=chromium.desktop;\n\
+             \n\
+             [Default Applications]\n\
+             x-scheme-handler/http=firefox.desktop;chromium.desktop;\n",
+        );
+        assert_eq!(
+            mimeapps_list.get_default_application("x-scheme-handler/http"),
+            Some("firefox.desktop".to_string())
+        );
+        assert_eq!(
+            mimeapps_list.get_default_application("x-scheme-handler/https"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_set_default_application_keeps_other_lines() {
+        let mut mimeapps_list = MimeAppsList::parse(
+            "# my comment\n\
+             [Default Applications]\n\
+             x-scheme-handler/http=firefox.desktop;\n\
+             image/png=gimp.desktop;\n\
+             \n\
+             [Added Associations]\n\
+             x-scheme-handler/http=firefox.desktop;\n",
+        );
+        mimeapps_list.set_default_application("x-scheme-handler/http", "software.Browsers.desktop");
+        mimeapps_list.set_default_application("text/html", "software.Browsers.desktop");
+
+        assert_eq!(
+            mimeapps_list.to_file_content(),
+            "# my comment\n\
+             [Default Applications]\n\
+             x-scheme-handler/http=software.Browsers.desktop;\n\
+             image/png=gimp.desktop;\n\
+             text/html=software.Browsers.desktop;\n\
+             \n\
+             [Added Associations]\n\
+             x-scheme-handler/http=software.Browsers.desktop;firefox.desktop;\n\
+             text/html=software.Browsers.desktop;\n"
+        );
+    }
+
+    #[test]
+    fn test_set_default_application_creates_groups() {
+        let mut mimeapps_list = MimeAppsList::parse("");
+        mimeapps_list.set_default_application("text/html", "software.Browsers.desktop");
+
+        assert_eq!(
+            mimeapps_list.to_file_content(),
+            "[Default Applications]\n\
+             text/html=software.Browsers.desktop;\n\
+             \n\
+             [Added Associations]\n\
+             text/html=software.Browsers.desktop;\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_default_application() {
+        let mut mimeapps_list = MimeAppsList::parse(
+            "[Default Applications]\n\
+             x-scheme-handler/http=software.Browsers.desktop;\n\
+             image/png=gimp.desktop;\n",
+        );
+        mimeapps_list.remove_default_application("x-scheme-handler/http");
+
+        assert_eq!(
+            mimeapps_list.to_file_content(),
+            "[Default Applications]\n\
+             image/png=gimp.desktop;\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_association() {
+        let mut mimeapps_list = MimeAppsList::parse(
+            "[Added Associations]\n\
+             x-scheme-handler/http=software.Browsers.desktop;firefox.desktop;\n\
+             x-scheme-handler/https=software.Browsers.desktop;\n\
+             image/png=gimp.desktop;\n",
+        );
+        mimeapps_list.remove_association("x-scheme-handler/http", "software.Browsers.desktop");
+        mimeapps_list.remove_association("x-scheme-handler/https", "software.Browsers.desktop");
+        mimeapps_list.remove_association("image/png", "software.Browsers.desktop");
+
+        assert_eq!(
+            mimeapps_list.to_file_content(),
+            "[Added Associations]\n\
+             x-scheme-handler/http=firefox.desktop;\n\
+             image/png=gimp.desktop;\n"
+        );
+    }
+}
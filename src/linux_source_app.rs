@@ -0,0 +1,382 @@
+// Finds out which application opened the link, so that rules with `source_app` work on Linux.
+//
+// Unlike macOS, Linux doesn't tell us who asked to open the url. Instead we walk the chain of
+// parent processes (xdg-open, gio, sh, ..., the app itself) and for every process look at
+//  - GIO_LAUNCHED_DESKTOP_FILE and BAMF_DESKTOP_FILE_HINT in its environment, which are set
+//    by GLib and Unity/Plank for apps launched from a .desktop file, and inherited by children
+//  - its executable (/proc/<pid>/exe) and argv[0] (/proc/<pid>/cmdline), which are matched
+//    against the Exec line of installed .desktop files
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+const DESKTOP_FILE_ENV_VARS: [&'static str; 2] =
+    ["GIO_LAUNCHED_DESKTOP_FILE", "BAMF_DESKTOP_FILE_HINT"];
+
+// wrappers which launch the real app or open the link on behalf of it
+const LAUNCHER_EXECUTABLES: [&'static str; 8] = [
+    "env", "sh", "bash", "dash", "xdg-open", "gio", "flatpak", "snap",
+];
+
+// don't walk forever if /proc is weird
+const MAX_PARENT_PROCESSES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopApp {
+    id: String,
+    executable: String,
+}
+
+impl DesktopApp {
+    pub fn new(id: &str, executable: &str) -> Self {
+        return Self {
+            id: id.to_string(),
+            executable: executable.to_string(),
+        };
+    }
+}
+
+pub struct SourceAppDetector {
+    proc_root: PathBuf,
+    own_desktop_id: String,
+    desktop_apps: Vec<DesktopApp>,
+}
+
+impl SourceAppDetector {
+    pub fn new(proc_root: &Path, own_desktop_id: &str, desktop_apps: Vec<DesktopApp>) -> Self {
+        return Self {
+            proc_root: proc_root.to_path_buf(),
+            own_desktop_id: own_desktop_id.to_string(),
+            desktop_apps: desktop_apps,
+        };
+    }
+
+    // returns desktop id (e.g "slack.desktop") of the app which launched process `pid`
+    // (the process itself is checked too, because env is inherited from the opener)
+    pub fn detect(&self, pid: u32) -> Option<String> {
+        let mut pid_maybe = Some(pid);
+        for _ in 0..MAX_PARENT_PROCESSES {
+            let current_pid = pid_maybe.filter(|pid| *pid > 1)?;
+            let process_dir = self.proc_root.join(current_pid.to_string());
+
+            let desktop_id_maybe = self
+                .desktop_id_from_environ(process_dir.as_path())
+                .or_else(|| self.desktop_id_from_executable(process_dir.as_path()));
+            if let Some(desktop_id) = desktop_id_maybe {
+                debug!("Detected source app {} from process {}", desktop_id, current_pid);
+                return Some(desktop_id);
+            }
+
+            pid_maybe = read_parent_pid(process_dir.as_path());
+        }
+
+        return None;
+    }
+
+    fn desktop_id_from_environ(&self, process_dir: &Path) -> Option<String> {
+        let environ = fs::read(process_dir.join("environ")).ok()?;
+        let environ = parse_environ(environ.as_slice());
+
+        return DESKTOP_FILE_ENV_VARS
+            .iter()
+            .filter_map(|env_var| environ.get(*env_var))
+            .filter_map(|desktop_file| desktop_file_to_id(desktop_file.as_str()))
+            .find(|desktop_id| !self.is_own_desktop_id(desktop_id));
+    }
+
+    fn desktop_id_from_executable(&self, process_dir: &Path) -> Option<String> {
+        let mut executables: Vec<String> = Vec::new();
+
+        let exe_result = fs::read_link(process_dir.join("exe"));
+        if let Ok(exe) = exe_result {
+            executables.extend(executable_name(exe.to_string_lossy().as_ref()));
+        }
+
+        let cmdline = fs::read(process_dir.join("cmdline")).unwrap_or_default();
+        let argv0 = cmdline.split(|byte| *byte == 0).next().unwrap_or_default();
+        executables.extend(executable_name(String::from_utf8_lossy(argv0).as_ref()));
+
+        return executables
+            .iter()
+            .filter(|executable| !LAUNCHER_EXECUTABLES.contains(&executable.as_str()))
+            .find_map(|executable| self.find_desktop_id_by_executable(executable.as_str()));
+    }
+
+    fn find_desktop_id_by_executable(&self, executable: &str) -> Option<String> {
+        let by_exec_line = self
+            .desktop_apps
+            .iter()
+            .find(|app| app.executable == executable && !self.is_own_desktop_id(&app.id));
+        if let Some(app) = by_exec_line {
+            return Some(app.id.clone());
+        }
+
+        // e.g /usr/lib/firefox/firefox -> firefox.desktop, even if Exec is a wrapper script
+        let by_id = self.desktop_apps.iter().find(|app| {
+            app.id.strip_suffix(".desktop") == Some(executable) && !self.is_own_desktop_id(&app.id)
+        });
+        return by_id.map(|app| app.id.clone());
+    }
+
+    fn is_own_desktop_id(&self, desktop_id: &str) -> bool {
+        return desktop_id == self.own_desktop_id;
+    }
+}
+
+// "PPid:\t1234" line in /proc/<pid>/status
+fn read_parent_pid(process_dir: &Path) -> Option<u32> {
+    let status = fs::read_to_string(process_dir.join("status")).ok()?;
+    return status
+        .lines()
+        .find_map(|line| line.strip_prefix("PPid:"))
+        .and_then(|ppid| ppid.trim().parse::<u32>().ok());
+}
+
+// /proc/<pid>/environ is KEY=value pairs separated by null bytes
+fn parse_environ(environ: &[u8]) -> HashMap<String, String> {
+    return environ
+        .split(|byte| *byte == 0)
+        .map(|entry| String::from_utf8_lossy(entry))
+        .filter_map(|entry| {
+            let (key, value) = entry.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect();
+}
+
+// /usr/share/applications/slack.desktop -> slack.desktop
+fn desktop_file_to_id(desktop_file: &str) -> Option<String> {
+    let file_name = Path::new(desktop_file).file_name()?.to_str()?;
+    return Some(file_name.to_string()).filter(|id| id.ends_with(".desktop"));
+}
+
+// /usr/lib/slack/slack -> slack
+// "/opt/app/app (deleted)" -> app, when the binary was updated while running
+fn executable_name(path: &str) -> Option<String> {
+    let path = path.trim_end_matches(" (deleted)");
+    let file_name = Path::new(path).file_name()?.to_str()?;
+    return Some(file_name.to_string()).filter(|name| !name.is_empty());
+}
+
+// all .desktop files in applications directories, earlier directories take precedence
+// see https://specifications.freedesktop.org/desktop-entry-spec/latest/ar01s02.html
+pub fn load_desktop_apps(applications_dirs: &[PathBuf]) -> Vec<DesktopApp> {
+    let mut desktop_apps: Vec<DesktopApp> = Vec::new();
+    for applications_dir in applications_dirs {
+        load_desktop_apps_from_dir(applications_dir.as_path(), "", &mut desktop_apps);
+    }
+    return desktop_apps;
+}
+
+// files in subdirectories get the directory as prefix, e.g kde4/konsole.desktop -> kde4-konsole.desktop
+fn load_desktop_apps_from_dir(dir: &Path, id_prefix: &str, desktop_apps: &mut Vec<DesktopApp>) {
+    let read_dir_result = fs::read_dir(dir);
+    if read_dir_result.is_err() {
+        return;
+    }
+
+    for entry in read_dir_result.unwrap().filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        if path.is_dir() {
+            let sub_prefix = format!("{}{}-", id_prefix, file_name);
+            load_desktop_apps_from_dir(path.as_path(), sub_prefix.as_str(), desktop_apps);
+            continue;
+        }
+
+        if !file_name.ends_with(".desktop") {
+            continue;
+        }
+
+        let desktop_id = format!("{}{}", id_prefix, file_name);
+        if desktop_apps.iter().any(|app| app.id == desktop_id) {
+            continue;
+        }
+
+        let content = fs::read_to_string(path.as_path()).unwrap_or_default();
+        if let Some(executable) = parse_exec_executable(content.as_str()) {
+            desktop_apps.push(DesktopApp::new(desktop_id.as_str(), executable.as_str()));
+        }
+    }
+}
+
+// name of the program in the Exec line of [Desktop Entry]
+// e.g `Exec=env GDK_BACKEND=x11 /usr/bin/slack %U` -> slack
+fn parse_exec_executable(desktop_file_content: &str) -> Option<String> {
+    let mut in_desktop_entry = false;
+    for line in desktop_file_content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_desktop_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_desktop_entry {
+            continue;
+        }
+
+        if let Some(exec) = line.strip_prefix("Exec=") {
+            return split_exec_args(exec)
+                .iter()
+                .find(|arg| *arg != "env" && !arg.contains('='))
+                .and_then(|program| executable_name(program));
+        }
+    }
+
+    return None;
+}
+
+// splits by whitespace, except inside double quotes
+fn split_exec_args(exec: &str) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    let mut current_arg = String::new();
+    let mut in_quotes = false;
+    for c in exec.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c.is_whitespace() && !in_quotes {
+            if !current_arg.is_empty() {
+                args.push(current_arg.clone());
+                current_arg.clear();
+            }
+        } else {
+            current_arg.push(c);
+        }
+    }
+    if !current_arg.is_empty() {
+        args.push(current_arg);
+    }
+    return args;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    struct FakeProc {
+        root: PathBuf,
+    }
+
+    impl FakeProc {
+        fn new(test_name: &str) -> Self {
+            let root =
+                env::temp_dir().join(format!("browsers-{}-{}", test_name, std::process::id()));
+            fs::remove_dir_all(root.as_path()).ok();
+            fs::create_dir_all(root.as_path()).unwrap();
+            return Self { root: root };
+        }
+
+        fn add_process(&self, pid: u32, ppid: u32, exe: &str, environ: &[&str]) {
+            let process_dir = self.root.join(pid.to_string());
+            fs::create_dir_all(process_dir.as_path()).unwrap();
+            fs::write(
+                process_dir.join("status"),
+                format!("Name:\tx\nPid:\t{}\nPPid:\t{}\n", pid, ppid),
+            )
+            .unwrap();
+            symlink(exe, process_dir.join("exe")).unwrap();
+            fs::write(process_dir.join("cmdline"), format!("{}\0--flag\0", exe)).unwrap();
+            fs::write(process_dir.join("environ"), environ.join("\0")).unwrap();
+        }
+    }
+
+    impl Drop for FakeProc {
+        fn drop(&mut self) {
+            fs::remove_dir_all(self.root.as_path()).ok();
+        }
+    }
+
+    fn detector(fake_proc: &FakeProc) -> SourceAppDetector {
+        let desktop_apps = vec![
+            DesktopApp::new("software.Browsers.desktop", "browsers"),
+            DesktopApp::new("slack.desktop", "slack"),
+            DesktopApp::new("org.telegram.desktop", "telegram-desktop"),
+            DesktopApp::new("firefox.desktop", "firefox.sh"),
+        ];
+        return SourceAppDetector::new(
+            fake_proc.root.as_path(),
+            "software.Browsers.desktop",
+            desktop_apps,
+        );
+    }
+
+    #[test]
+    fn test_detect_from_parent_executable() {
+        let fake_proc = FakeProc::new("test_detect_from_parent_executable");
+        fake_proc.add_process(1, 0, "/sbin/init", &[]);
+        fake_proc.add_process(100, 1, "/usr/lib/slack/slack", &[]);
+        fake_proc.add_process(200, 100, "/usr/bin/dash", &[]);
+        fake_proc.add_process(300, 200, "/usr/local/bin/browsers", &[]);
+
+        let detector = detector(&fake_proc);
+        assert_eq!(detector.detect(300), Some("slack.desktop".to_string()));
+        assert_eq!(detector.detect(1), None);
+    }
+
+    #[test]
+    fn test_detect_from_desktop_file_env() {
+        let fake_proc = FakeProc::new("test_detect_from_desktop_file_env");
+        fake_proc.add_process(1, 0, "/sbin/init", &[]);
+        fake_proc.add_process(
+            100,
+            1,
+            "/app/bin/Telegram",
+            &[
+                "HOME=/home/me",
+                "BAMF_DESKTOP_FILE_HINT=/var/lib/snapd/org.telegram.desktop",
+            ],
+        );
+        // launched via gio, so own desktop file is set and must be skipped
+        fake_proc.add_process(
+            200,
+            100,
+            "/usr/local/bin/browsers",
+            &["GIO_LAUNCHED_DESKTOP_FILE=/usr/share/applications/software.Browsers.desktop"],
+        );
+
+        let detector = detector(&fake_proc);
+        assert_eq!(detector.detect(200), Some("org.telegram.desktop".to_string()));
+    }
+
+    #[test]
+    fn test_detect_by_desktop_id_and_unknown() {
+        let fake_proc = FakeProc::new("test_detect_by_desktop_id_and_unknown");
+        fake_proc.add_process(1, 0, "/sbin/init", &[]);
+        fake_proc.add_process(100, 1, "/usr/lib/firefox/firefox (deleted)", &[]);
+        fake_proc.add_process(200, 100, "/usr/local/bin/browsers", &[]);
+        fake_proc.add_process(300, 1, "/usr/bin/some-unknown-app", &[]);
+        fake_proc.add_process(400, 300, "/usr/local/bin/browsers", &[]);
+
+        let detector = detector(&fake_proc);
+        assert_eq!(detector.detect(200), Some("firefox.desktop".to_string()));
+        assert_eq!(detector.detect(400), None);
+        // process has already exited
+        assert_eq!(detector.detect(500), None);
+    }
+
+    #[test]
+    fn test_parse_exec_executable() {
+        assert_eq!(
+            parse_exec_executable(
+                "[Desktop Entry]\n\
+                 Name=Slack\n\
+                 Exec=env GDK_BACKEND=x11 /usr/bin/slack %U\n\
+                 \n\
+                 [Desktop Action new]\n\
+                 Exec=/usr/bin/other\n"
+            ),
+            Some("slack".to_string())
+        );
+        assert_eq!(
+            parse_exec_executable("[Desktop Entry]\nExec=\"/opt/My App/app\" %u\n"),
+            Some("app".to_string())
+        );
+        assert_eq!(parse_exec_executable("[Desktop Action new]\nExec=x\n"), None);
+    }
+}
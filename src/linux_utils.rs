@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
@@ -9,9 +10,19 @@ use glib::AppInfo;
 use gtk::prelude::*;
 use gtk::{gio, IconLookupFlags, IconTheme};
 
+use crate::linux_mimeapps_list::MimeAppsList;
+use crate::linux_source_app::{load_desktop_apps, SourceAppDetector};
 use crate::{InstalledBrowser, SupportedAppRepository};
 
 const XDG_NAME: &'static str = "software.Browsers";
+const DESKTOP_ID: &'static str = "software.Browsers.desktop";
+
+// mime types which make an app the default web browser
+const WEB_BROWSER_MIME_TYPES: [&'static str; 3] = [
+    "x-scheme-handler/http",
+    "x-scheme-handler/https",
+    "text/html",
+];
 
 pub struct OsHelper {
     app_repository: SupportedAppRepository,
@@ -136,7 +147,7 @@ impl OsHelper {
         let id = id_gstring.as_str().to_string();
         // "google-chrome-beta.desktop"
 
-        if id == "software.Browsers.desktop" {
+        if id == DESKTOP_ID {
             // this is us, skip
             return None;
         }
@@ -240,6 +251,247 @@ fn create_icon_for_app(
     info!("icon: from {} to {}", original_icon_path_str, to_icon_path);
 }
 
+pub fn is_default_web_browser() -> bool {
+    // text/html is not checked, because it's fine if local html files open elsewhere
+    return ["x-scheme-handler/http", "x-scheme-handler/https"]
+        .iter()
+        .all(|mime_type| get_default_application(mime_type).as_deref() == Some(DESKTOP_ID));
+}
+
+// returns true if it was already default web browser (then nothing was done)
+pub fn set_default_web_browser() -> bool {
+    if is_default_web_browser() {
+        return true;
+    }
+
+    // remember previous handlers, so that they can be restored
+    let mut previous_handlers = load_previous_default_handlers();
+    for mime_type in WEB_BROWSER_MIME_TYPES {
+        let previous_handler_maybe = get_default_application(mime_type);
+        if let Some(previous_handler) = previous_handler_maybe {
+            if previous_handler != DESKTOP_ID {
+                previous_handlers.insert(mime_type.to_string(), previous_handler);
+            }
+        }
+    }
+    save_previous_default_handlers(&previous_handlers);
+
+    for mimeapps_list_path in get_writable_mimeapps_list_paths() {
+        update_mimeapps_list(mimeapps_list_path.as_path(), |mimeapps_list| {
+            for mime_type in WEB_BROWSER_MIME_TYPES {
+                mimeapps_list.set_default_application(mime_type, DESKTOP_ID);
+            }
+        });
+    }
+
+    return false;
+}
+
+// restores handlers which were default before Browsers was made default
+pub fn unset_default_web_browser() {
+    let previous_handlers = load_previous_default_handlers();
+
+    for mimeapps_list_path in get_writable_mimeapps_list_paths() {
+        update_mimeapps_list(mimeapps_list_path.as_path(), |mimeapps_list| {
+            for mime_type in WEB_BROWSER_MIME_TYPES {
+                let current_handler_maybe = mimeapps_list.get_default_application(mime_type);
+                if current_handler_maybe.as_deref() != Some(DESKTOP_ID) {
+                    // not ours, leave as is
+                    continue;
+                }
+
+                let previous_handler_maybe = previous_handlers.get(mime_type);
+                if let Some(previous_handler) = previous_handler_maybe {
+                    mimeapps_list.set_default_application(mime_type, previous_handler);
+                } else {
+                    mimeapps_list.remove_default_application(mime_type);
+                }
+                // also not first in associations anymore, where set_default_web_browser put it
+                mimeapps_list.remove_association(mime_type, DESKTOP_ID);
+            }
+        });
+    }
+
+    let previous_handlers_path = get_previous_default_handlers_path();
+    if previous_handlers_path.exists() {
+        fs::remove_file(previous_handlers_path.as_path()).ok();
+    }
+}
+
+// Returns default application for mime type,
+// the same way as desktop environments resolve it from mimeapps.list files
+fn get_default_application(mime_type: &str) -> Option<String> {
+    for mimeapps_list_path in get_mimeapps_list_paths() {
+        let mimeapps_list_maybe = read_mimeapps_list(mimeapps_list_path.as_path());
+        let default_maybe = mimeapps_list_maybe
+            .and_then(|mimeapps_list| mimeapps_list.get_default_application(mime_type));
+        if default_maybe.is_some() {
+            return default_maybe;
+        }
+    }
+
+    return None;
+}
+
+fn read_mimeapps_list(path: &Path) -> Option<MimeAppsList> {
+    if !path.exists() {
+        return None;
+    }
+
+    let content_result = fs::read_to_string(path);
+    if content_result.is_err() {
+        warn!("Could not read {}", path.display());
+        return None;
+    }
+
+    return Some(MimeAppsList::parse(content_result.unwrap().as_str()));
+}
+
+fn update_mimeapps_list(path: &Path, update_fn: impl Fn(&mut MimeAppsList)) {
+    let mut mimeapps_list = read_mimeapps_list(path).unwrap_or_default();
+    update_fn(&mut mimeapps_list);
+
+    if let Some(parent_dir) = path.parent() {
+        fs::create_dir_all(parent_dir).ok();
+    }
+    let write_result = fs::write(path, mimeapps_list.to_file_content());
+    if write_result.is_err() {
+        warn!(
+            "Could not write {}: {}",
+            path.display(),
+            write_result.unwrap_err()
+        );
+        return;
+    }
+    info!("Updated {}", path.display());
+}
+
+// $XDG_CURRENT_DESKTOP=ubuntu:GNOME -> ["ubuntu", "gnome"]
+fn get_current_desktops() -> Vec<String> {
+    return std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|desktop| !desktop.is_empty())
+        .map(|desktop| desktop.to_lowercase())
+        .collect();
+}
+
+fn get_xdg_dirs(env_var_name: &str, default_value: &str) -> Vec<PathBuf> {
+    let value = std::env::var(env_var_name).unwrap_or_default();
+    let value = if value.is_empty() {
+        default_value.to_string()
+    } else {
+        value
+    };
+
+    return value
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir))
+        .collect();
+}
+
+// mimeapps.list files in the order of precedence
+// see https://specifications.freedesktop.org/mime-apps-spec/latest/ar01s02.html
+fn get_mimeapps_list_paths() -> Vec<PathBuf> {
+    let mut base_dirs: Vec<PathBuf> = Vec::new();
+    // $XDG_CONFIG_HOME or $HOME/.config
+    base_dirs.push(dirs::config_dir().unwrap());
+    base_dirs.extend(get_xdg_dirs("XDG_CONFIG_DIRS", "/etc/xdg"));
+    // $XDG_DATA_HOME or $HOME/.local/share
+    base_dirs.push(dirs::data_dir().unwrap().join("applications"));
+    base_dirs.extend(
+        get_xdg_dirs("XDG_DATA_DIRS", "/usr/local/share:/usr/share")
+            .iter()
+            .map(|dir| dir.join("applications")),
+    );
+
+    let desktops = get_current_desktops();
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for dir in base_dirs {
+        for desktop in desktops.iter() {
+            paths.push(dir.join(format!("{}-mimeapps.list", desktop)));
+        }
+        paths.push(dir.join("mimeapps.list"));
+    }
+    return paths;
+}
+
+// $HOME/.config/mimeapps.list and desktop specific files, which would otherwise take precedence
+fn get_writable_mimeapps_list_paths() -> Vec<PathBuf> {
+    let config_dir = dirs::config_dir().unwrap();
+
+    let mut paths: Vec<PathBuf> = get_current_desktops()
+        .iter()
+        .map(|desktop| config_dir.join(format!("{}-mimeapps.list", desktop)))
+        .filter(|path| path.exists())
+        .collect();
+    paths.push(config_dir.join("mimeapps.list"));
+    return paths;
+}
+
+// $HOME/.local/state/software.Browsers/previous_default_handlers.json
+fn get_previous_default_handlers_path() -> PathBuf {
+    return get_this_app_xdg_state_dir().join("previous_default_handlers.json");
+}
+
+fn load_previous_default_handlers() -> HashMap<String, String> {
+    let path = get_previous_default_handlers_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+
+    let content = fs::read_to_string(path.as_path()).unwrap_or_default();
+    return serde_json::from_str(content.as_str()).unwrap_or_default();
+}
+
+fn save_previous_default_handlers(previous_handlers: &HashMap<String, String>) {
+    let path = get_previous_default_handlers_path();
+    fs::create_dir_all(get_this_app_xdg_state_dir()).ok();
+
+    let content = serde_json::to_string_pretty(previous_handlers).unwrap();
+    let write_result = fs::write(path.as_path(), content);
+    if write_result.is_err() {
+        warn!("Could not save previous default handlers to {}", path.display());
+    }
+}
+
+// $XDG_DATA_HOME/applications and $XDG_DATA_DIRS/applications in the order of precedence
+fn get_applications_dirs() -> Vec<PathBuf> {
+    let mut applications_dirs: Vec<PathBuf> = Vec::new();
+    applications_dirs.push(dirs::data_dir().unwrap().join("applications"));
+    applications_dirs.extend(
+        get_xdg_dirs("XDG_DATA_DIRS", "/usr/local/share:/usr/share")
+            .iter()
+            .map(|dir| dir.join("applications")),
+    );
+    return applications_dirs;
+}
+
+// desktop id of the app which opened the link, e.g "slack.desktop"
+pub fn detect_source_app() -> Option<String> {
+    let desktop_apps = load_desktop_apps(get_applications_dirs().as_slice());
+    let detector = SourceAppDetector::new(Path::new("/proc"), DESKTOP_ID, desktop_apps);
+
+    let source_app_maybe = detector.detect(std::process::id());
+    if let Some(ref source_app) = source_app_maybe {
+        info!("Link was opened from {}", source_app);
+    }
+    return source_app_maybe;
+}
+
+// hostname of this machine, without domain
+pub fn get_hostname() -> Option<String> {
+    let hostname_result = fs::read_to_string("/proc/sys/kernel/hostname");
+    if hostname_result.is_err() {
+        warn!("Could not read hostname from /proc/sys/kernel/hostname");
+        return None;
+    }
+    let hostname = hostname_result.unwrap().trim().to_string();
+    return Some(hostname).filter(|hostname| !hostname.is_empty());
+}
+
 // $HOME/.config/software.Browsers
 pub fn get_this_app_config_root_dir() -> PathBuf {
     return get_this_app_xdg_config_dir();
@@ -292,6 +544,17 @@ fn get_this_app_xdg_config_dir() -> PathBuf {
     return dirs::config_dir().unwrap().join(XDG_NAME);
 }
 
+// $XDG_CONFIG_DIRS/software.Browsers, e.g /etc/xdg/software.Browsers
+// for config managed by administrators; the most important dir is last
+pub fn get_this_app_system_config_dirs() -> Vec<PathBuf> {
+    // first one in $XDG_CONFIG_DIRS is the most important
+    return get_xdg_dirs("XDG_CONFIG_DIRS", "/etc/xdg")
+        .into_iter()
+        .rev()
+        .map(|dir| dir.join(XDG_NAME))
+        .collect();
+}
+
 // $HOME/.local/share/software.Browsers
 fn get_this_app_xdg_data_dir() -> PathBuf {
     // $XDG_DATA_HOME or $HOME/.local/share
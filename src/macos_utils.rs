@@ -473,6 +473,27 @@ pub fn get_this_app_config_root_dir() -> PathBuf {
     return get_this_app_support_dir();
 }
 
+// /Library/Application Support/software.Browsers
+// for config managed by administrators
+pub fn get_this_app_system_config_dirs() -> Vec<PathBuf> {
+    return vec![PathBuf::from("/Library/Application Support").join(APP_DIR_NAME)];
+}
+
+// hostname of this machine, e.g "my-macbook.local"
+pub fn get_hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    let result =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        warn!("Could not get hostname");
+        return None;
+    }
+
+    let length = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    let hostname = String::from_utf8_lossy(&buffer[..length]).to_string();
+    return Some(hostname).filter(|hostname| !hostname.is_empty());
+}
+
 /*pub fn find_bundle_ids_for_browsers() -> Vec<String> {
     let bundle_ids_for_https = get_bundle_ids_for_url_scheme("https");
 
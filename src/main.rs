@@ -1,5 +1,6 @@
 #![windows_subsystem = "windows"]
 
+use std::process::ExitCode;
 use std::str::FromStr;
 use std::sync::mpsc;
 use std::{env, fs};
@@ -12,11 +13,13 @@ use tracing_subscriber;
 use tracing_subscriber::fmt::time::OffsetTime;
 use tracing_subscriber::fmt::writer::MakeWriterExt;
 
+use browsers::cli::CliCommand;
 use browsers::communicate;
 use browsers::paths;
-use browsers::{basically_main, MessageToMain};
+use browsers::utils;
+use browsers::{basically_main, cli, MessageToMain};
 
-fn main() {
+fn main() -> ExitCode {
     let offset_time = OffsetTime::local_rfc_3339().expect("could not get local offset!");
 
     let logs_root_dir = paths::get_logs_root_dir();
@@ -43,9 +46,26 @@ fn main() {
         env::set_var("RUST_BACKTRACE", "full");
     }
 
+    let args: Vec<String> = env::args().collect();
+    //info!("{:?}", args);
+
+    // exits early if args are invalid or --help/--version is used
+    let cli_command = cli::parse_args(args);
+
+    // keep stdout clean for output of cli commands, they still log to file
+    let log_to_stdout = matches!(
+        cli_command,
+        CliCommand::Gui { .. }
+            | CliCommand::Open {
+                profile: None,
+                dry_run: false,
+                ..
+            }
+    );
+
     tracing_subscriber::fmt()
         .with_timer(offset_time)
-        .with_writer(non_blocking.and(std::io::stdout))
+        .with_writer(non_blocking.and(std::io::stdout.with_filter(move |_| log_to_stdout)))
         .with_max_level(log_level)
         .with_ansi(false)
         .init();
@@ -53,29 +73,50 @@ fn main() {
     info!("Starting Browsers");
     info!("Logging to {}", log_file_path.display());
 
-    let args: Vec<String> = env::args().collect();
-    //info!("{:?}", args);
-
-    let mut url = "".to_string();
-    let url_input_maybe = args.iter().find(|i| i.starts_with("http"));
-    if let Some(url_input) = url_input_maybe {
-        url = url_input.to_string();
+    match cli_command {
+        CliCommand::Gui {
+            url,
+            show_gui,
+            force_reload,
+        } => run_gui(url.as_str(), None, show_gui, force_reload),
+        CliCommand::Open {
+            url,
+            profile: None,
+            // --incognito requires --profile, otherwise cli::run_command reports it
+            incognito: false,
+            source_app,
+            dry_run: false,
+        } => run_gui(url.as_str(), source_app, true, false),
+        cli_command => {
+            let exit_code = cli::run_command(cli_command);
+            return ExitCode::from(exit_code as u8);
+        }
     }
 
-    let show_gui = !args.contains(&"--no-gui".to_string());
-    let force_reload = args.contains(&"--reload".to_string());
+    return ExitCode::SUCCESS;
+}
 
+fn run_gui(url: &str, source_app_maybe: Option<String>, show_gui: bool, force_reload: bool) {
     let (main_sender, main_receiver) = mpsc::channel::<MessageToMain>();
 
+    // --source-app wins over the detected one. A running instance is sent the opener, so it's
+    // detected here then; otherwise basically_main detects it only when a rule needs it.
+    let get_opener = || {
+        source_app_maybe
+            .clone()
+            .or_else(|| utils::detect_source_app())
+            .unwrap_or_default()
+    };
     let (is_first_instance, single_instance) =
-        communicate::check_single_instance(url.as_str(), main_sender.clone());
+        communicate::check_single_instance(url, get_opener, main_sender.clone());
     if !is_first_instance {
         info!("Exiting, because another instance is running");
         return;
     }
 
     basically_main(
-        url.as_str(),
+        url,
+        source_app_maybe,
         show_gui,
         force_reload,
         main_sender.clone(),
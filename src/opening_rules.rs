@@ -0,0 +1,1278 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use druid::Data;
+use time::OffsetDateTime;
+use tracing::{info, instrument, warn};
+use url::Url;
+
+use crate::rule_actions::{ActionError, ProfileCandidates, RuleAction};
+use crate::rule_conditions::{
+    Clock, ConditionError, ConditionMatch, ConfigEnvironment, Environment, EnvironmentConditions,
+    LazySourceApp, Schedule, SourceAppMatcher, SourceAppPatterns,
+};
+use crate::url_rule;
+use crate::url_rule::{
+    QueryParamCondition, UrlGlobMatcher, UrlMatchExplanation, UrlPart, UrlPatternError, UrlRegex,
+};
+use crate::utils::{ConfigRule, ConfigRuleEntry, ProfileAndOptions, RuleMatching};
+
+#[derive(Debug)]
+pub struct OpeningRule {
+    // position of the rule in config, to refer to it in logs and cli output
+    index: usize,
+    source_app: Option<SourceAppPatterns>,
+    not_source_app: Option<SourceAppPatterns>,
+    // compiled once from source_app and not_source_app
+    source_app_matcher: Option<SourceAppMatcher>,
+    not_source_app_matcher: Option<SourceAppMatcher>,
+    url_pattern: Option<String>,
+    url_regex: Option<UrlRegex>,
+    query_params: BTreeMap<String, QueryParamCondition>,
+    // compiled once from url_pattern or url_regex, and query_params
+    url_matcher: Option<UrlGlobMatcher>,
+    schedule: Option<Schedule>,
+    config_environment: Option<ConfigEnvironment>,
+    // compiled once from config_environment
+    environment_conditions: Option<EnvironmentConditions>,
+    // from action, or profile and incognito
+    action: RuleAction,
+    priority: i32,
+    specificity: Specificity,
+}
+
+// How specific the conditions of a rule are, used when rule_matching is most_specific.
+// Compared first by the url pattern, then by the number of other conditions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Specificity {
+    pub url_pattern: usize,
+    pub other_conditions: usize,
+}
+
+// Why a rule from config can't be used
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleError {
+    // rule doesn't match the config format, e.g unknown action type or wrong type of a value
+    Format(String),
+    UrlPattern(UrlPatternError),
+    Condition(ConditionError),
+    Action(ActionError),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Format(message) => write!(f, "{}", message),
+            RuleError::UrlPattern(url_pattern_error) => url_pattern_error.fmt(f),
+            RuleError::Condition(condition_error) => condition_error.fmt(f),
+            RuleError::Action(action_error) => action_error.fmt(f),
+        }
+    }
+}
+
+impl From<UrlPatternError> for RuleError {
+    fn from(url_pattern_error: UrlPatternError) -> Self {
+        return RuleError::UrlPattern(url_pattern_error);
+    }
+}
+
+impl From<ConditionError> for RuleError {
+    fn from(condition_error: ConditionError) -> Self {
+        return RuleError::Condition(condition_error);
+    }
+}
+
+impl From<ActionError> for RuleError {
+    fn from(action_error: ActionError) -> Self {
+        return RuleError::Action(action_error);
+    }
+}
+
+impl OpeningRule {
+    pub fn from_config_rule(index: usize, config_rule: &ConfigRule) -> Result<Self, RuleError> {
+        let url_matcher = match (&config_rule.url_pattern, &config_rule.url_regex) {
+            (Some(url_pattern), None) => Some(url_rule::to_url_glob_matcher(url_pattern.as_str())?),
+            (None, Some(url_regex)) => Some(url_rule::to_url_regex_matcher(url_regex)?),
+            (Some(url_pattern), Some(_)) => {
+                return Err(RuleError::UrlPattern(UrlPatternError::new(
+                    url_pattern.as_str(),
+                    UrlPart::Url,
+                    "url_pattern and url_regex can't be used in the same rule",
+                )));
+            }
+            (None, None) => None,
+        };
+
+        let url_matcher = if !config_rule.query_params.is_empty() {
+            let url_matcher = url_matcher.unwrap_or_else(url_rule::any_url_matcher);
+            Some(url_matcher.with_query_params(&config_rule.query_params)?)
+        } else {
+            url_matcher
+        };
+
+        let source_app_matcher = if let Some(ref source_app) = config_rule.source_app {
+            Some(SourceAppMatcher::from_patterns("source_app", source_app)?)
+        } else {
+            None
+        };
+        let not_source_app_matcher = if let Some(ref not_source_app) = config_rule.not_source_app {
+            Some(SourceAppMatcher::from_patterns(
+                "not_source_app",
+                not_source_app,
+            )?)
+        } else {
+            None
+        };
+
+        let schedule = if let Some(ref config_schedule) = config_rule.schedule {
+            Some(Schedule::from_config_schedule(config_schedule)?)
+        } else {
+            None
+        };
+
+        let environment_conditions = if let Some(ref config_environment) = config_rule.environment {
+            Some(EnvironmentConditions::from_config_environment(
+                config_environment,
+            )?)
+        } else {
+            None
+        };
+
+        let action = match config_rule.action {
+            Some(ref action) if !config_rule.profile.is_empty() || config_rule.incognito => {
+                return Err(RuleError::Action(ActionError {
+                    action: action.name(),
+                    reason: "action can't be used together with profile or incognito",
+                }));
+            }
+            Some(ref action) => {
+                action.validate()?;
+                action.clone()
+            }
+            None => RuleAction::OpenProfile {
+                profile: config_rule.profile.clone(),
+                incognito: config_rule.incognito,
+            },
+        };
+
+        let url_pattern_specificity = if let Some(ref url_pattern) = config_rule.url_pattern {
+            url_rule::url_pattern_specificity(url_pattern.as_str())
+        } else if let Some(ref url_regex) = config_rule.url_regex {
+            url_rule::url_regex_specificity(url_regex)
+        } else {
+            0
+        };
+        let source_app_condition_count =
+            source_app_matcher.iter().count() + not_source_app_matcher.iter().count();
+        let schedule_condition_count = if schedule.is_some() { 1 } else { 0 };
+        let specificity = Specificity {
+            url_pattern: url_pattern_specificity,
+            other_conditions: config_rule.query_params.len()
+                + source_app_condition_count
+                + schedule_condition_count
+                + environment_conditions
+                    .as_ref()
+                    .map_or(0, |environment_conditions| {
+                        environment_conditions.conditions_count()
+                    }),
+        };
+
+        return Ok(Self {
+            index: index,
+            source_app: config_rule.source_app.clone(),
+            not_source_app: config_rule.not_source_app.clone(),
+            source_app_matcher: source_app_matcher,
+            not_source_app_matcher: not_source_app_matcher,
+            url_pattern: config_rule.url_pattern.clone(),
+            url_regex: config_rule.url_regex.clone(),
+            query_params: config_rule.query_params.clone(),
+            url_matcher: url_matcher,
+            schedule: schedule,
+            config_environment: config_rule.environment.clone(),
+            environment_conditions: environment_conditions,
+            action: action,
+            priority: config_rule.priority,
+            specificity: specificity,
+        });
+    }
+
+    pub fn get_index(&self) -> usize {
+        return self.index;
+    }
+
+    pub fn get_source_app(&self) -> Option<&SourceAppPatterns> {
+        return self.source_app.as_ref();
+    }
+
+    pub fn get_not_source_app(&self) -> Option<&SourceAppPatterns> {
+        return self.not_source_app.as_ref();
+    }
+
+    pub fn get_url_pattern(&self) -> Option<&String> {
+        return self.url_pattern.as_ref();
+    }
+
+    pub fn get_url_regex(&self) -> Option<&UrlRegex> {
+        return self.url_regex.as_ref();
+    }
+
+    pub fn get_query_params(&self) -> &BTreeMap<String, QueryParamCondition> {
+        return &self.query_params;
+    }
+
+    pub fn has_source_app_condition(&self) -> bool {
+        return self.source_app_matcher.is_some() || self.not_source_app_matcher.is_some();
+    }
+
+    pub fn get_priority(&self) -> i32 {
+        return self.priority;
+    }
+
+    pub fn get_specificity(&self) -> Specificity {
+        return self.specificity;
+    }
+
+    // rules are checked from highest to lowest rank
+    fn rank(&self, rule_matching: RuleMatching) -> (i32, Specificity) {
+        return match rule_matching {
+            RuleMatching::FirstMatch => (self.priority, Specificity::default()),
+            RuleMatching::MostSpecific => (self.priority, self.specificity),
+        };
+    }
+
+    fn has_same_conditions(&self, other: &OpeningRule) -> bool {
+        return self.source_app == other.source_app
+            && self.not_source_app == other.not_source_app
+            && self.url_pattern == other.url_pattern
+            && self.url_regex == other.url_regex
+            && self.query_params == other.query_params
+            && self.schedule == other.schedule
+            && self.config_environment == other.config_environment;
+    }
+
+    // whether both rules can match the same url, judged by their url patterns only
+    fn can_overlap(&self, other: &OpeningRule) -> bool {
+        return match (&self.url_pattern, &other.url_pattern) {
+            (Some(url_pattern), Some(other_url_pattern)) => {
+                url_rule::can_url_patterns_overlap(url_pattern, other_url_pattern)
+            }
+            // any url, or url_regex
+            _ => true,
+        };
+    }
+
+    pub fn get_action(&self) -> &RuleAction {
+        return &self.action;
+    }
+
+    pub fn get_schedule(&self) -> Option<&Schedule> {
+        return self.schedule.as_ref();
+    }
+
+    pub fn evaluate(
+        &self,
+        url: &Url,
+        source_app_maybe: Option<&String>,
+        now: &OffsetDateTime,
+        environment: &dyn Environment,
+    ) -> RuleEvaluation {
+        // no url pattern means any url
+        let url_match_maybe = self
+            .url_matcher
+            .as_ref()
+            .map(|url_matcher| url_matcher.explain_url_match(url));
+
+        // e.g on Linux source app is often not known
+        let source_app_maybe = source_app_maybe.filter(|source_app| !source_app.is_empty());
+        let source_app_match = if let Some(ref source_app_matcher) = self.source_app_matcher {
+            source_app_maybe.map_or(false, |source_app| source_app_matcher.is_match(source_app))
+        } else {
+            true
+        };
+        // unknown source app is not any of the excluded apps
+        let not_source_app_match = if let Some(ref not_source_app_matcher) =
+            self.not_source_app_matcher
+        {
+            source_app_maybe.map_or(true, |source_app| !not_source_app_matcher.is_match(source_app))
+        } else {
+            true
+        };
+
+        // no schedule means any time
+        let schedule_match = self
+            .schedule
+            .as_ref()
+            .map_or(true, |schedule| schedule.is_active(now));
+
+        // no environment conditions means any machine
+        let environment_match_maybe = self
+            .environment_conditions
+            .as_ref()
+            .map(|environment_conditions| environment_conditions.explain_match(environment));
+
+        return RuleEvaluation {
+            url_match: url_match_maybe,
+            source_app_match: source_app_match && not_source_app_match,
+            schedule_match: schedule_match,
+            environment_match: environment_match_maybe,
+        };
+    }
+}
+
+// Result of matching a single rule, kept for explaining why rule did (not) match
+#[derive(Debug, Clone)]
+pub struct RuleEvaluation {
+    // None if rule has no url pattern
+    pub url_match: Option<UrlMatchExplanation>,
+    pub source_app_match: bool,
+    pub schedule_match: bool,
+    // None if rule has no environment conditions
+    pub environment_match: Option<Vec<ConditionMatch>>,
+}
+
+impl RuleEvaluation {
+    pub fn is_match(&self) -> bool {
+        let url_matches = self
+            .url_match
+            .as_ref()
+            .map_or(true, |url_match| url_match.is_match());
+        let environment_matches =
+            self.environment_match
+                .as_ref()
+                .map_or(true, |condition_matches| {
+                    condition_matches
+                        .iter()
+                        .all(|condition_match| condition_match.matches)
+                });
+        return url_matches && self.source_app_match && self.schedule_match && environment_matches;
+    }
+}
+
+// A rule from config which can't be used
+#[derive(Debug, Clone)]
+pub struct InvalidRule {
+    pub index: usize,
+    pub error: RuleError,
+}
+
+// Two rules which are equally ranked, so only their order in config decides which one is used
+#[derive(Debug, Clone)]
+pub struct RuleTie {
+    // index of the rule which is used, when both match
+    pub index: usize,
+    pub other_index: usize,
+    // conditions are exactly the same, so the other rule is never used
+    pub same_conditions: bool,
+}
+
+// Profile id in a rule which doesn't exist (anymore), e.g after a browser was reinstalled
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingProfile {
+    pub index: usize,
+    pub profile_id: String,
+    // next candidate profile of the rule which exists and is used instead
+    pub fallback_profile_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RulesValidationReport {
+    pub rules_count: usize,
+    pub invalid_rules: Vec<InvalidRule>,
+    pub ties: Vec<RuleTie>,
+    // filled in by find_missing_profiles(), as profiles are not known when compiling rules
+    pub missing_profiles: Vec<MissingProfile>,
+}
+
+impl RulesValidationReport {
+    pub fn is_valid(&self) -> bool {
+        return self.invalid_rules.is_empty();
+    }
+
+    // human readable summary, one problem per line
+    pub fn summary_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for invalid_rule in &self.invalid_rules {
+            lines.push(format!(
+                "rule #{} is skipped: {}",
+                invalid_rule.index + 1,
+                invalid_rule.error
+            ));
+        }
+        for missing_profile in &self.missing_profiles {
+            let consequence = match missing_profile.fallback_profile_id {
+                Some(ref fallback_profile_id) => format!("{} is used instead", fallback_profile_id),
+                None => "it is not used".to_string(),
+            };
+            lines.push(format!(
+                "rule #{} has profile {} which doesn't exist, {}",
+                missing_profile.index + 1,
+                missing_profile.profile_id,
+                consequence
+            ));
+        }
+        for tie in &self.ties {
+            if tie.same_conditions {
+                lines.push(format!(
+                    "rule #{} is never used: rule #{} has the same conditions and priority",
+                    tie.other_index + 1,
+                    tie.index + 1
+                ));
+            } else {
+                lines.push(format!(
+                    "rules #{} and #{} have the same priority and specificity, rule #{} is used when both match",
+                    tie.index + 1,
+                    tie.other_index + 1,
+                    tie.index + 1
+                ));
+            }
+        }
+        return lines;
+    }
+}
+
+// Compiles rules from config; invalid rules are skipped (and reported),
+// so that a typo in one rule doesn't break all the others.
+// Returned rules are sorted in the order they must be checked.
+pub fn compile_rules(
+    config_rules: &[ConfigRuleEntry],
+    rule_matching: RuleMatching,
+) -> (Vec<OpeningRule>, RulesValidationReport) {
+    let mut opening_rules: Vec<OpeningRule> = Vec::new();
+    let mut report = RulesValidationReport {
+        rules_count: config_rules.len(),
+        invalid_rules: vec![],
+        ties: vec![],
+        missing_profiles: vec![],
+    };
+
+    for (index, config_rule_entry) in config_rules.iter().enumerate() {
+        let opening_rule_result = match config_rule_entry {
+            ConfigRuleEntry::Valid(config_rule) => {
+                OpeningRule::from_config_rule(index, config_rule)
+            }
+            // parsed again only to tell what is wrong with it
+            ConfigRuleEntry::Invalid(value) => {
+                let format_error = serde_json::from_value::<ConfigRule>(value.clone())
+                    .err()
+                    .map(|error| error.to_string())
+                    .unwrap_or_default();
+                Err(RuleError::Format(format_error))
+            }
+        };
+        if opening_rule_result.is_err() {
+            report.invalid_rules.push(InvalidRule {
+                index: index,
+                error: opening_rule_result.unwrap_err(),
+            });
+            continue;
+        }
+        opening_rules.push(opening_rule_result.unwrap());
+    }
+
+    // stable sort, so equally ranked rules keep their order in config
+    opening_rules.sort_by_key(|opening_rule| Reverse(opening_rule.rank(rule_matching)));
+    report.ties = find_ties(&opening_rules, rule_matching);
+
+    for line in report.summary_lines() {
+        warn!("{}", line);
+    }
+    if !report.is_valid() {
+        info!(
+            "{} of {} rules are invalid and skipped",
+            report.invalid_rules.len(),
+            report.rules_count
+        );
+    }
+
+    return (opening_rules, report);
+}
+
+// Profile ids in rules which are not among existing (visible or hidden) profiles
+pub fn find_missing_profiles(
+    opening_rules: &[OpeningRule],
+    existing_profile_ids: &[String],
+) -> Vec<MissingProfile> {
+    let mut missing_profiles: Vec<MissingProfile> = Vec::new();
+    for opening_rule in opening_rules {
+        let profile_ids = opening_rule.action.get_profile_ids();
+        let is_fallback_chain = matches!(opening_rule.action, RuleAction::OpenProfile { .. });
+
+        for (i, profile_id) in profile_ids.iter().enumerate() {
+            // empty profile is the picker
+            if profile_id.is_empty() || existing_profile_ids.contains(profile_id) {
+                continue;
+            }
+
+            let fallback_profile_id = if is_fallback_chain {
+                profile_ids[i + 1..]
+                    .iter()
+                    .find(|candidate| existing_profile_ids.contains(candidate))
+                    .cloned()
+            } else {
+                None
+            };
+            missing_profiles.push(MissingProfile {
+                index: opening_rule.index,
+                profile_id: profile_id.clone(),
+                fallback_profile_id: fallback_profile_id,
+            });
+        }
+    }
+    return missing_profiles;
+}
+
+// Equally ranked rules which go to different profiles and can match the same url.
+// In first_match mode only rules with exactly the same conditions are reported,
+// because there order of rules is expected to decide.
+fn find_ties(sorted_rules: &[OpeningRule], rule_matching: RuleMatching) -> Vec<RuleTie> {
+    let mut ties: Vec<RuleTie> = Vec::new();
+    for (i, rule) in sorted_rules.iter().enumerate() {
+        for other_rule in sorted_rules[i + 1..].iter() {
+            if other_rule.rank(rule_matching) != rule.rank(rule_matching) {
+                break;
+            }
+
+            if rule.action == other_rule.action {
+                continue;
+            }
+
+            let same_conditions = rule.has_same_conditions(other_rule);
+            let is_reported = same_conditions
+                || (rule_matching == RuleMatching::MostSpecific && rule.can_overlap(other_rule));
+            if is_reported {
+                ties.push(RuleTie {
+                    index: rule.index,
+                    other_index: other_rule.index,
+                    same_conditions: same_conditions,
+                });
+            }
+        }
+    }
+    return ties;
+}
+
+// opening_rules must be in the order returned by compile_rules()
+#[instrument(skip_all)]
+pub fn get_rule_for_source_app_and_url(
+    opening_rules: &Vec<OpeningRule>,
+    default_profile_maybe: Option<ProfileAndOptions>,
+    url: &str,
+    source_app: &LazySourceApp,
+    clock: &dyn Clock,
+    environment: &dyn Environment,
+) -> Option<RuleAction> {
+    let url_result = Url::from_str(url);
+    if url_result.is_err() {
+        return None;
+    }
+    let given_url = url_result.unwrap();
+    let now = clock.now();
+
+    for r in opening_rules {
+        // rules without source app conditions don't need it to be detected
+        let source_app_maybe = if r.has_source_app_condition() {
+            source_app.get()
+        } else {
+            None
+        };
+        let rule_evaluation = r.evaluate(&given_url, source_app_maybe, &now, environment);
+        if rule_evaluation.is_match() {
+            return Some(r.get_action().clone());
+        }
+    }
+
+    return default_profile_maybe
+        .map(|default_profile| RuleAction::from_profile_and_options(&default_profile));
+}
+
+// What a remembered choice from the picker applies to
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum RememberScope {
+    // "docs.github.com"
+    Host,
+    // "**.github.com", which is github.com and all its subdomains
+    RegistrableDomain,
+    // "docs.github.com/en/**"
+    HostAndPathPrefix,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RememberError {
+    // url has no hostname, no path when path prefix was asked,
+    // or no registrable domain when domain was asked
+    UnsupportedUrl(String),
+    // index of the existing rule with the same url pattern
+    DuplicateRule(usize),
+}
+
+impl fmt::Display for RememberError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            RememberError::UnsupportedUrl(url) => {
+                write!(f, "can't create a rule for url {}", url)
+            }
+            RememberError::DuplicateRule(index) => {
+                write!(f, "rule #{} already exists for this url", index + 1)
+            }
+        };
+    }
+}
+
+// Creates a rule which opens links like `url` in the chosen profile.
+// Refuses to duplicate an existing rule with the same url pattern and no other conditions,
+// even if it goes to another profile, because only one of them would ever be used.
+pub fn create_remembered_rule(
+    existing_rules: &[ConfigRuleEntry],
+    url: &str,
+    scope: RememberScope,
+    profile_and_options: &ProfileAndOptions,
+) -> Result<ConfigRule, RememberError> {
+    let url_pattern = remembered_url_pattern(url, scope)
+        .ok_or_else(|| RememberError::UnsupportedUrl(url.to_string()))?;
+
+    let duplicate_index_maybe = existing_rules.iter().position(|rule_entry| {
+        // rule which can't be read is not used, so it's not a duplicate
+        return rule_entry.get_rule().map_or(false, |rule| {
+            let same_url_pattern = rule
+                .url_pattern
+                .as_ref()
+                .filter(|pattern| pattern.eq_ignore_ascii_case(url_pattern.as_str()))
+                .is_some();
+            same_url_pattern && has_only_url_pattern(rule)
+        });
+    });
+    if let Some(duplicate_index) = duplicate_index_maybe {
+        return Err(RememberError::DuplicateRule(duplicate_index));
+    }
+
+    return Ok(ConfigRule {
+        url_pattern: Some(url_pattern),
+        profile: ProfileCandidates::One(profile_and_options.profile.clone()),
+        incognito: profile_and_options.incognito,
+        ..ConfigRule::default()
+    });
+}
+
+// whether a choice for this url can be remembered with this scope
+pub fn can_remember(url: &str, scope: RememberScope) -> bool {
+    return remembered_url_pattern(url, scope).is_some();
+}
+
+fn remembered_url_pattern(url: &str, scope: RememberScope) -> Option<String> {
+    let url = Url::from_str(url).ok()?;
+    let hostname = url.host_str().filter(|hostname| !hostname.is_empty())?;
+
+    return match scope {
+        RememberScope::Host => Some(hostname.to_string()),
+        RememberScope::RegistrableDomain => {
+            // not guessed for ip addresses and unknown suffixes, as the rule could
+            // then apply to unrelated sites
+            let domain = url_rule::registrable_domain(hostname)?;
+            Some(format!("**.{}", domain))
+        }
+        RememberScope::HostAndPathPrefix => {
+            // first segment of the path, e.g "/en" of "/en/get-started/quickstart"
+            let first_segment = url
+                .path_segments()?
+                .next()
+                .filter(|segment| !segment.is_empty())?;
+            let path_prefix = url_rule::escape_glob(first_segment);
+            Some(format!("{}/{}/**", hostname, path_prefix))
+        }
+    };
+}
+
+fn has_only_url_pattern(rule: &ConfigRule) -> bool {
+    return rule.source_app.is_none()
+        && rule.not_source_app.is_none()
+        && rule.url_regex.is_none()
+        && rule.query_params.is_empty()
+        && rule.schedule.is_none()
+        && rule.environment.is_none();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use time::macros::datetime;
+
+    use crate::rule_conditions::{ConfigSchedule, SystemClock, SystemEnvironment};
+    use crate::utils::Config;
+
+    use super::*;
+
+    struct FixedClock(OffsetDateTime);
+
+    struct FakeEnvironment(&'static str);
+
+    impl Environment for FakeEnvironment {
+        fn hostname(&self) -> Option<String> {
+            return Some(self.0.to_string());
+        }
+
+        fn var(&self, _key: &str) -> Option<String> {
+            return None;
+        }
+    }
+
+    impl Clock for FixedClock {
+        fn now(&self) -> OffsetDateTime {
+            return self.0;
+        }
+    }
+
+    fn config_rule(url_pattern: &str, profile: &str, priority: i32) -> ConfigRule {
+        return ConfigRule {
+            url_pattern: Some(url_pattern.to_string()),
+            profile: ProfileCandidates::One(profile.to_string()),
+            priority: priority,
+            ..ConfigRule::default()
+        };
+    }
+
+    fn entries(config_rules: &[ConfigRule]) -> Vec<ConfigRuleEntry> {
+        return config_rules
+            .iter()
+            .cloned()
+            .map(ConfigRuleEntry::from)
+            .collect();
+    }
+
+    fn profile_for_url(opening_rules: &Vec<OpeningRule>, url: &str) -> Option<String> {
+        return get_rule_for_source_app_and_url(
+            opening_rules,
+            None,
+            url,
+            &LazySourceApp::known(None),
+            &SystemClock,
+            &SystemEnvironment,
+        )
+        .map(|action| action.description());
+    }
+
+    #[test]
+    fn test_first_match_uses_order_in_config() {
+        let config_rules = vec![
+            config_rule("*.corp.com", "work", 0),
+            config_rule("*.corp.com/admin/**", "admin", 0),
+        ];
+        let (opening_rules, report) =
+            compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+
+        assert_eq!(
+            profile_for_url(&opening_rules, "https://intra.corp.com/admin/users"),
+            Some("work".to_string())
+        );
+        assert_eq!(report.ties.len(), 0);
+    }
+
+    #[test]
+    fn test_most_specific_wins_regardless_of_order() {
+        let config_rules = vec![
+            config_rule("*.corp.com", "work", 0),
+            config_rule("*.corp.com/admin/**", "admin", 0),
+        ];
+        let (opening_rules, _) = compile_rules(&entries(&config_rules), RuleMatching::MostSpecific);
+
+        assert_eq!(
+            profile_for_url(&opening_rules, "https://intra.corp.com/admin/users"),
+            Some("admin".to_string())
+        );
+        assert_eq!(
+            profile_for_url(&opening_rules, "https://intra.corp.com/wiki"),
+            Some("work".to_string())
+        );
+    }
+
+    #[test]
+    fn test_higher_priority_wins() {
+        let config_rules = vec![
+            config_rule("*.corp.com/admin/**", "admin", 0),
+            config_rule("*.corp.com", "work", 10),
+        ];
+
+        for rule_matching in [RuleMatching::FirstMatch, RuleMatching::MostSpecific] {
+            let (opening_rules, _) = compile_rules(&entries(&config_rules), rule_matching);
+            assert_eq!(
+                profile_for_url(&opening_rules, "https://intra.corp.com/admin/users"),
+                Some("work".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_ties_are_reported() {
+        let config_rules = vec![
+            config_rule("*.corp.com", "work", 0),
+            config_rule("*.corp.org", "personal", 0),
+            config_rule("*.corp.com", "other", 0),
+            // same profile is not a problem
+            config_rule("*.corp.net", "work", 0),
+        ];
+
+        let (_, report) = compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+        assert_eq!(report.ties.len(), 1);
+        assert_eq!(report.ties[0].index, 0);
+        assert_eq!(report.ties[0].other_index, 2);
+        assert_eq!(report.ties[0].same_conditions, true);
+        assert_eq!(
+            report.summary_lines(),
+            vec!["rule #3 is never used: rule #1 has the same conditions and priority"]
+        );
+
+        let (_, report) = compile_rules(&entries(&config_rules), RuleMatching::MostSpecific);
+        let ties: Vec<(usize, usize)> = report
+            .ties
+            .iter()
+            .map(|tie| (tie.index, tie.other_index))
+            .collect();
+        assert_eq!(ties, vec![(0, 2)]);
+        assert_eq!(report.is_valid(), true);
+    }
+
+    #[test]
+    fn test_ties_only_for_overlapping_hostnames() {
+        let config_rules = vec![
+            config_rule("**.corp.com", "work", 0),
+            config_rule("*.corp.com", "other", 0),
+            config_rule("*.corp.org", "personal", 0),
+            config_rule("docs.corp.net", "work", 0),
+            config_rule("wiki.corp.net", "other", 0),
+        ];
+
+        let (_, report) = compile_rules(&entries(&config_rules), RuleMatching::MostSpecific);
+        let ties: Vec<(usize, usize)> = report
+            .ties
+            .iter()
+            .map(|tie| (tie.index, tie.other_index))
+            .collect();
+        assert_eq!(ties, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_schedule_decides_between_work_and_personal_profile() {
+        let work_hours = ConfigSchedule {
+            weekdays: vec![
+                "mon".to_string(),
+                "tue".to_string(),
+                "wed".to_string(),
+                "thu".to_string(),
+                "fri".to_string(),
+            ],
+            from: Some("09:00".to_string()),
+            to: Some("17:00".to_string()),
+            timezone: Some("Europe/London".to_string()),
+        };
+        let config_rules = vec![
+            ConfigRule {
+                schedule: Some(work_hours),
+                ..config_rule("*.google.com", "work", 0)
+            },
+            config_rule("*.google.com", "personal", 0),
+        ];
+        let (opening_rules, report) =
+            compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+        assert_eq!(report.ties.len(), 0);
+
+        let profile_at = |now: OffsetDateTime| {
+            get_rule_for_source_app_and_url(
+                &opening_rules,
+                None,
+                "https://mail.google.com/",
+                &LazySourceApp::known(None),
+                &FixedClock(now),
+                &SystemEnvironment,
+            )
+            .map(|action| action.description())
+        };
+
+        // Tuesday 10:00 in London
+        assert_eq!(
+            profile_at(datetime!(2023-08-15 09:00 UTC)),
+            Some("work".to_string())
+        );
+        // Tuesday 20:00 in London
+        assert_eq!(
+            profile_at(datetime!(2023-08-15 19:00 UTC)),
+            Some("personal".to_string())
+        );
+        // Sunday 10:00 in London
+        assert_eq!(
+            profile_at(datetime!(2023-08-20 09:00 UTC)),
+            Some("personal".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_schedule_skips_rule() {
+        let config_rules = vec![ConfigRule {
+            schedule: Some(ConfigSchedule {
+                from: Some("25:00".to_string()),
+                ..ConfigSchedule::default()
+            }),
+            ..config_rule("*.google.com", "work", 0)
+        }];
+        let (opening_rules, report) =
+            compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+
+        assert_eq!(opening_rules.len(), 0);
+        assert_eq!(
+            report.summary_lines(),
+            vec![
+                "rule #1 is skipped: invalid schedule from \"25:00\": hour or minute out of range"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_rule_skips_only_that_rule() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "hidden_profiles": ["firefox.desktop#default"],
+                "rules": [
+                    {"url_pattern": "*.corp.com", "profile": "work"},
+                    {"url_pattern": "*.example.com", "action": {"type": "teleport"}},
+                    {"url_pattern": "*.example.org", "profile": "work", "priority": "high"},
+                    "github.com",
+                    {"url_pattern": "github.com", "profile": "personal"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.get_hidden_profiles(), &vec!["firefox.desktop#default"]);
+
+        let (opening_rules, report) = compile_rules(config.get_rules(), config.get_rule_matching());
+        assert_eq!(opening_rules.len(), 2);
+        assert_eq!(
+            profile_for_url(&opening_rules, "https://github.com/"),
+            Some("personal".to_string())
+        );
+        let invalid_indexes: Vec<usize> = report
+            .invalid_rules
+            .iter()
+            .map(|invalid_rule| invalid_rule.index)
+            .collect();
+        assert_eq!(invalid_indexes, vec![1, 2, 3]);
+        assert_eq!(
+            report.summary_lines()[0].starts_with("rule #2 is skipped: unknown variant `teleport`"),
+            true
+        );
+
+        // kept as they are when config is saved
+        let saved_config = serde_json::to_value(&config).unwrap();
+        assert_eq!(saved_config["rules"][1]["action"]["type"], "teleport");
+        assert_eq!(saved_config["rules"][3], "github.com");
+    }
+
+    #[test]
+    fn test_environment_decides_profile_per_machine() {
+        let config_rules = vec![
+            ConfigRule {
+                environment: Some(ConfigEnvironment {
+                    hostname: Some("workstation-*".to_string()),
+                    ..ConfigEnvironment::default()
+                }),
+                ..config_rule("*.corp.com", "shared", 0)
+            },
+            config_rule("*.corp.com", "work", 0),
+        ];
+        let (opening_rules, _) = compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+
+        let profile_on = |hostname: &'static str| {
+            get_rule_for_source_app_and_url(
+                &opening_rules,
+                None,
+                "https://intra.corp.com/",
+                &LazySourceApp::known(None),
+                &SystemClock,
+                &FakeEnvironment(hostname),
+            )
+            .map(|action| action.description())
+        };
+
+        assert_eq!(profile_on("workstation-3"), Some("shared".to_string()));
+        assert_eq!(profile_on("my-laptop"), Some("work".to_string()));
+    }
+
+    #[test]
+    fn test_source_app_globs_lists_and_negation() {
+        let config_rules = vec![
+            ConfigRule {
+                source_app: Some(SourceAppPatterns::Many(vec![
+                    "slack*.desktop".to_string(),
+                    "org.telegram.*".to_string(),
+                ])),
+                profile: ProfileCandidates::One("personal".to_string()),
+                ..ConfigRule::default()
+            },
+            ConfigRule {
+                not_source_app: Some(SourceAppPatterns::One("*thunderbird*".to_string())),
+                profile: ProfileCandidates::One("work".to_string()),
+                ..ConfigRule::default()
+            },
+        ];
+        let (opening_rules, _) = compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+
+        let profile_from = |source_app: &str| {
+            get_rule_for_source_app_and_url(
+                &opening_rules,
+                Some(ProfileAndOptions {
+                    profile: "default".to_string(),
+                    incognito: false,
+                }),
+                "https://example.com/",
+                &LazySourceApp::known(Some(source_app.to_string())),
+                &SystemClock,
+                &SystemEnvironment,
+            )
+            .map(|action| action.description())
+        };
+
+        assert_eq!(profile_from("slack.desktop"), Some("personal".to_string()));
+        assert_eq!(
+            profile_from("org.telegram.desktop"),
+            Some("personal".to_string())
+        );
+        assert_eq!(profile_from("code.desktop"), Some("work".to_string()));
+        // unknown source app
+        assert_eq!(profile_from(""), Some("work".to_string()));
+        assert_eq!(
+            profile_from("org.mozilla.Thunderbird.desktop"),
+            Some("default".to_string())
+        );
+    }
+
+    #[test]
+    fn test_source_app_is_detected_only_for_source_app_rules() {
+        let detect_count = Rc::new(Cell::new(0));
+        let detect_count_clone = detect_count.clone();
+        let source_app = LazySourceApp::detected_with(move || {
+            detect_count_clone.set(detect_count_clone.get() + 1);
+            Some("slack.desktop".to_string())
+        });
+
+        let config_rules = vec![config_rule("*.corp.com", "work", 0)];
+        let (opening_rules, _) = compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+        let rule_action_maybe = get_rule_for_source_app_and_url(
+            &opening_rules,
+            None,
+            "https://example.com/",
+            &source_app,
+            &SystemClock,
+            &SystemEnvironment,
+        );
+        assert_eq!(rule_action_maybe, None);
+        assert_eq!(detect_count.get(), 0);
+
+        let config_rules = vec![
+            ConfigRule {
+                source_app: Some(SourceAppPatterns::One("slack*".to_string())),
+                profile: ProfileCandidates::One("personal".to_string()),
+                ..ConfigRule::default()
+            },
+            ConfigRule {
+                source_app: Some(SourceAppPatterns::One("code*".to_string())),
+                profile: ProfileCandidates::One("work".to_string()),
+                ..ConfigRule::default()
+            },
+        ];
+        let (opening_rules, _) = compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+        let rule_action_maybe = get_rule_for_source_app_and_url(
+            &opening_rules,
+            None,
+            "https://example.com/",
+            &source_app,
+            &SystemClock,
+            &SystemEnvironment,
+        );
+        assert_eq!(rule_action_maybe.unwrap().description(), "personal");
+        // detected once, even when more rules need it
+        assert_eq!(detect_count.get(), 1);
+    }
+
+    #[test]
+    fn test_create_remembered_rule() {
+        let work = ProfileAndOptions {
+            profile: "work".to_string(),
+            incognito: false,
+        };
+        let url = "https://docs.github.com/en/get-started?x=1";
+        let url_pattern_for = |scope: RememberScope| {
+            create_remembered_rule(&[], url, scope, &work)
+                .unwrap()
+                .url_pattern
+                .unwrap()
+        };
+
+        assert_eq!(url_pattern_for(RememberScope::Host), "docs.github.com");
+        assert_eq!(
+            url_pattern_for(RememberScope::RegistrableDomain),
+            "**.github.com"
+        );
+        assert_eq!(
+            url_pattern_for(RememberScope::HostAndPathPrefix),
+            "docs.github.com/en/**"
+        );
+
+        let remembered_rule = create_remembered_rule(&[], url, RememberScope::Host, &work).unwrap();
+        assert_eq!(remembered_rule.profile.to_string(), "work");
+        let (opening_rules, _) =
+            compile_rules(&entries(&[remembered_rule]), RuleMatching::FirstMatch);
+        assert_eq!(profile_for_url(&opening_rules, url), Some("work".to_string()));
+
+        assert_eq!(
+            create_remembered_rule(&[], "mailto:me@example.com", RememberScope::Host, &work)
+                .unwrap_err(),
+            RememberError::UnsupportedUrl("mailto:me@example.com".to_string())
+        );
+        assert_eq!(
+            create_remembered_rule(
+                &[],
+                "https://github.com/",
+                RememberScope::HostAndPathPrefix,
+                &work
+            )
+            .unwrap_err(),
+            RememberError::UnsupportedUrl("https://github.com/".to_string())
+        );
+
+        // the suffix of the domain is not known, so only the host can be remembered
+        let local_url = "http://printer.lan/status";
+        assert_eq!(can_remember(local_url, RememberScope::Host), true);
+        assert_eq!(can_remember(local_url, RememberScope::RegistrableDomain), false);
+        assert_eq!(
+            can_remember("http://127.0.0.1:3000/", RememberScope::RegistrableDomain),
+            false
+        );
+    }
+
+    #[test]
+    fn test_create_remembered_rule_refuses_duplicate() {
+        let existing_rules = vec![
+            ConfigRule {
+                source_app: Some(SourceAppPatterns::One("slack.desktop".to_string())),
+                ..config_rule("github.com", "personal", 0)
+            },
+            config_rule("GitHub.com", "personal", 0),
+        ];
+        let work = ProfileAndOptions {
+            profile: "work".to_string(),
+            incognito: false,
+        };
+
+        let result = create_remembered_rule(
+            &entries(&existing_rules),
+            "https://github.com/",
+            RememberScope::Host,
+            &work,
+        );
+        let error = result.unwrap_err();
+        assert_eq!(error, RememberError::DuplicateRule(1));
+        assert_eq!(error.to_string(), "rule #2 already exists for this url");
+
+        // only the first rule with extra conditions exists
+        let result = create_remembered_rule(
+            &entries(&existing_rules[..1]),
+            "https://github.com/",
+            RememberScope::Host,
+            &work,
+        );
+        assert_eq!(result.is_ok(), true);
+    }
+
+    #[test]
+    fn test_rule_actions() {
+        let config_rules = vec![
+            ConfigRule {
+                action: Some(RuleAction::CopyToClipboard),
+                ..config_rule("*.zoom.us", "", 0)
+            },
+            ConfigRule {
+                action: Some(RuleAction::Block { message: None }),
+                ..config_rule("ads.example.com", "work", 0)
+            },
+            ConfigRule {
+                action: Some(RuleAction::Picker { profiles: vec![] }),
+                ..config_rule("picker.example.com", "", 0)
+            },
+            config_rule("example.com", "work", 0),
+        ];
+        let (opening_rules, report) =
+            compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+
+        let action_for = |url: &str| {
+            get_rule_for_source_app_and_url(
+                &opening_rules,
+                None,
+                url,
+                &LazySourceApp::known(None),
+                &SystemClock,
+                &SystemEnvironment,
+            )
+        };
+        assert_eq!(
+            action_for("https://us02web.zoom.us/j/1"),
+            Some(RuleAction::CopyToClipboard)
+        );
+        assert_eq!(
+            action_for("https://example.com/"),
+            Some(RuleAction::OpenProfile {
+                profile: ProfileCandidates::One("work".to_string()),
+                incognito: false,
+            })
+        );
+
+        let invalid_rule_errors: Vec<String> = report
+            .invalid_rules
+            .iter()
+            .map(|invalid_rule| invalid_rule.error.to_string())
+            .collect();
+        assert_eq!(
+            invalid_rule_errors,
+            vec![
+                "invalid block action: action can't be used together with profile or incognito",
+                "invalid picker action: profiles must not be empty",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_missing_profiles() {
+        let config_rules = vec![
+            config_rule("a.example.com", "gone", 0),
+            ConfigRule {
+                profile: ProfileCandidates::Many(vec![
+                    "new-work".to_string(),
+                    "old-work".to_string(),
+                    "work".to_string(),
+                ]),
+                ..config_rule("b.example.com", "", 0)
+            },
+            ConfigRule {
+                action: Some(RuleAction::Picker {
+                    profiles: vec!["work".to_string(), "gone".to_string()],
+                }),
+                ..config_rule("c.example.com", "", 0)
+            },
+            config_rule("d.example.com", "work", 0),
+            config_rule("e.example.com", "", 0),
+        ];
+        let (opening_rules, mut report) =
+            compile_rules(&entries(&config_rules), RuleMatching::FirstMatch);
+        report.missing_profiles = find_missing_profiles(&opening_rules, &["work".to_string()]);
+
+        assert_eq!(
+            report.summary_lines(),
+            vec![
+                "rule #1 has profile gone which doesn't exist, it is not used",
+                "rule #2 has profile new-work which doesn't exist, work is used instead",
+                "rule #2 has profile old-work which doesn't exist, work is used instead",
+                "rule #3 has profile gone which doesn't exist, it is not used",
+            ]
+        );
+    }
+}
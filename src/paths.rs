@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use crate::config_formats;
 #[cfg(target_os = "linux")]
 use crate::linux_utils;
 #[cfg(target_os = "macos")]
@@ -41,6 +42,12 @@ pub fn get_config_json_path() -> PathBuf {
     return get_config_root_dir().join("config.json");
 }
 
+// user's config file, which can also be config.toml or config.yaml, see config_formats
+pub fn get_config_file_path() -> PathBuf {
+    return config_formats::find_config_file(get_config_root_dir().as_path())
+        .unwrap_or(get_config_json_path());
+}
+
 #[cfg(target_os = "macos")]
 pub fn get_config_root_dir() -> PathBuf {
     return macos_utils::get_this_app_config_root_dir();
@@ -56,6 +63,22 @@ pub fn get_config_root_dir() -> PathBuf {
     return windows_utils::get_this_app_config_root_dir();
 }
 
+// config files which are merged before config.json, see config_layers
+#[cfg(target_os = "macos")]
+pub fn get_system_config_dirs() -> Vec<PathBuf> {
+    return macos_utils::get_this_app_system_config_dirs();
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_system_config_dirs() -> Vec<PathBuf> {
+    return linux_utils::get_this_app_system_config_dirs();
+}
+
+#[cfg(target_os = "windows")]
+pub fn get_system_config_dirs() -> Vec<PathBuf> {
+    return windows_utils::get_this_app_system_config_dirs();
+}
+
 #[cfg(target_os = "macos")]
 pub fn get_chrome_user_dir_root() -> PathBuf {
     return macos_utils::macos_get_unsandboxed_application_support_dir();
@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::InstalledBrowser;
+
+// copy of config.json before legacy ids in it were replaced
+pub const LEGACY_IDS_CONFIG_BACKUP_FILENAME: &'static str = "config.before-stable-ids.json";
+
+// legacy id -> stable id
+//
+// Ids used to be based on the executable path (e.g "/usr/bin/firefox#default"),
+// which changes when the app moves, e.g from /usr/bin to /opt.
+// Now they are based on the desktop id / bundle id (e.g "firefox.desktop#default").
+pub type IdAliases = BTreeMap<String, String>;
+
+// Stable id of every installed app, in the same order.
+// Desktop id in Linux, bundle id in macOS, app name in Windows.
+// If the same app is installed more than once, the first one found keeps the plain id,
+// so installing or removing another copy doesn't change it. The executable path is added
+// to the ids of the other copies to tell them apart.
+pub fn stable_app_ids(installed_browsers: &[InstalledBrowser]) -> Vec<String> {
+    let mut seen_bundles: HashSet<&str> = HashSet::new();
+
+    return installed_browsers
+        .iter()
+        .map(|installed_browser| {
+            let bundle = installed_browser.bundle.as_str();
+            if bundle.is_empty() {
+                return installed_browser.executable_path.clone();
+            }
+            if !seen_bundles.insert(bundle) {
+                return format!("{}@{}", bundle, installed_browser.executable_path);
+            }
+            return bundle.to_string();
+        })
+        .collect();
+}
+
+// replaces a legacy id with its stable id, the used alias is added to used_aliases
+pub fn rename_id(id: &mut String, aliases: &IdAliases, used_aliases: &mut IdAliases) -> bool {
+    if let Some(stable_id) = aliases.get(id.as_str()) {
+        used_aliases.insert(id.clone(), stable_id.clone());
+        *id = stable_id.clone();
+        return true;
+    }
+    return false;
+}
+
+// Id without the executable path of a duplicated app, which differs between machines, e.g
+// "org.mozilla.firefox@/Applications/Firefox.app#default" -> "org.mozilla.firefox#default"
+pub fn portable_id(id: &str) -> String {
+    let (app_id, profile_part) = split_app_id(id);
+    let portable_app_id = match app_id.split_once('@') {
+        // not an executable path which has @ in it
+        Some((bundle, _)) if !bundle.is_empty() && !bundle.contains(['/', '\\']) => bundle,
+        _ => app_id,
+    };
+    return portable_app_id.to_string() + profile_part;
+}
+
+// Id of the app installed on this machine for a portable id, e.g with the executable path
+// added back if the app is installed more than once (the first one is used).
+// Ids of apps which are not installed are not changed.
+pub fn local_id(portable_id: &str, app_ids: &[String]) -> String {
+    let (app_id, profile_part) = split_app_id(portable_id);
+    if app_ids
+        .iter()
+        .any(|installed_app_id| installed_app_id == app_id)
+    {
+        return portable_id.to_string();
+    }
+
+    let duplicate_prefix = app_id.to_string() + "@";
+    return match app_ids
+        .iter()
+        .find(|installed_app_id| installed_app_id.starts_with(duplicate_prefix.as_str()))
+    {
+        Some(installed_app_id) => installed_app_id.clone() + profile_part,
+        None => portable_id.to_string(),
+    };
+}
+
+// app id is before the first #, profile part is the rest including #
+pub fn split_app_id(id: &str) -> (&str, &str) {
+    return match id.find('#') {
+        Some(index) => id.split_at(index),
+        None => (id, ""),
+    };
+}
+
+// e.g when both the legacy and the stable id of a profile were hidden
+pub fn remove_duplicates(ids: &mut Vec<String>) {
+    let mut unique_ids: Vec<String> = Vec::with_capacity(ids.len());
+    for id in ids.drain(..) {
+        if !unique_ids.contains(&id) {
+            unique_ids.push(id);
+        }
+    }
+    *ids = unique_ids;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Config;
+
+    fn installed_browser(bundle: &str, executable_path: &str) -> InstalledBrowser {
+        return InstalledBrowser {
+            command: vec![executable_path.to_string()],
+            executable_path: executable_path.to_string(),
+            display_name: "".to_string(),
+            bundle: bundle.to_string(),
+            user_dir: "".to_string(),
+            icon_path: "".to_string(),
+            profiles: vec![],
+            restricted_domains: vec![],
+        };
+    }
+
+    #[test]
+    fn test_stable_app_ids() {
+        let installed_browsers = vec![
+            installed_browser("firefox.desktop", "/usr/bin/firefox"),
+            installed_browser("org.mozilla.firefox", "/Applications/Firefox.app"),
+            installed_browser("org.mozilla.firefox", "/Users/x/Applications/Firefox.app"),
+            installed_browser("", "/opt/browser/browser"),
+        ];
+
+        assert_eq!(
+            stable_app_ids(&installed_browsers),
+            vec![
+                "firefox.desktop",
+                "org.mozilla.firefox",
+                "org.mozilla.firefox@/Users/x/Applications/Firefox.app",
+                "/opt/browser/browser",
+            ]
+        );
+
+        // the first copy keeps its id when another copy is removed
+        assert_eq!(
+            stable_app_ids(&installed_browsers[..2]),
+            vec!["firefox.desktop", "org.mozilla.firefox"]
+        );
+    }
+
+    #[test]
+    fn test_rename_id() {
+        let aliases = IdAliases::from([(
+            "/usr/bin/firefox#default".to_string(),
+            "firefox.desktop#default".to_string(),
+        )]);
+        let mut used_aliases = IdAliases::new();
+
+        let mut id = "slack.desktop#T123".to_string();
+        assert_eq!(rename_id(&mut id, &aliases, &mut used_aliases), false);
+        assert_eq!(used_aliases.is_empty(), true);
+
+        let mut id = "/usr/bin/firefox#default".to_string();
+        assert_eq!(rename_id(&mut id, &aliases, &mut used_aliases), true);
+        assert_eq!(id, "firefox.desktop#default");
+        assert_eq!(used_aliases, aliases);
+    }
+
+    #[test]
+    fn test_portable_and_local_id() {
+        assert_eq!(
+            portable_id("org.mozilla.firefox@/Applications/Firefox.app#default"),
+            "org.mozilla.firefox#default"
+        );
+        assert_eq!(
+            portable_id("firefox.desktop#default#Work"),
+            "firefox.desktop#default#Work"
+        );
+        assert_eq!(
+            portable_id("org.mozilla.firefox@/Applications/Firefox.app"),
+            "org.mozilla.firefox"
+        );
+        assert_eq!(
+            portable_id("/home/x@y/browser#default"),
+            "/home/x@y/browser#default"
+        );
+
+        let app_ids = vec![
+            "firefox.desktop".to_string(),
+            "org.mozilla.firefox@/Applications/Firefox.app".to_string(),
+            "org.mozilla.firefox@/Users/x/Applications/Firefox.app".to_string(),
+        ];
+        assert_eq!(
+            local_id("firefox.desktop#default", &app_ids),
+            "firefox.desktop#default"
+        );
+        assert_eq!(
+            local_id("org.mozilla.firefox#default", &app_ids),
+            "org.mozilla.firefox@/Applications/Firefox.app#default"
+        );
+        assert_eq!(local_id("slack.desktop#T123", &app_ids), "slack.desktop#T123");
+    }
+
+    #[test]
+    fn test_remove_duplicates() {
+        let mut ids = vec![
+            "firefox.desktop#default".to_string(),
+            "slack.desktop#T123".to_string(),
+            "firefox.desktop#default".to_string(),
+        ];
+        remove_duplicates(&mut ids);
+        assert_eq!(ids, vec!["firefox.desktop#default", "slack.desktop#T123"]);
+    }
+
+    #[test]
+    fn test_migrate_config() {
+        let mut config: Config = serde_json::from_str(
+            r#"{
+                "hidden_apps": ["/usr/bin/firefox"],
+                "hidden_profiles": ["/usr/bin/google-chrome#Default", "google-chrome.desktop#Default"],
+                "profile_order": ["/usr/bin/google-chrome#Profile 1", "slack.desktop#T123"],
+                "default_profile": {"profile": "/usr/bin/google-chrome#Profile 1"},
+                "rules": [
+                    {"url_pattern": "a.com", "profile": ["/usr/bin/firefox#work", "slack.desktop#T123"]},
+                    {"url_pattern": "b.com", "action": {"type": "picker", "profiles": ["/usr/bin/firefox#work"]}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let aliases = IdAliases::from([
+            ("/usr/bin/firefox".to_string(), "firefox.desktop".to_string()),
+            (
+                "/usr/bin/firefox#work".to_string(),
+                "firefox.desktop#work".to_string(),
+            ),
+            (
+                "/usr/bin/google-chrome#Default".to_string(),
+                "google-chrome.desktop#Default".to_string(),
+            ),
+            (
+                "/usr/bin/google-chrome#Profile 1".to_string(),
+                "google-chrome.desktop#Profile 1".to_string(),
+            ),
+            (
+                "/usr/bin/google-chrome#Profile 2".to_string(),
+                "google-chrome.desktop#Profile 2".to_string(),
+            ),
+        ]);
+
+        assert_eq!(config.migrate_legacy_ids(&aliases), 6);
+        assert_eq!(config.get_hidden_apps(), &vec!["firefox.desktop"]);
+        assert_eq!(
+            config.get_hidden_profiles(),
+            &vec!["google-chrome.desktop#Default"]
+        );
+        assert_eq!(
+            config.get_profile_order(),
+            &vec!["google-chrome.desktop#Profile 1", "slack.desktop#T123"]
+        );
+        assert_eq!(
+            config.get_default_profile().as_ref().unwrap().profile,
+            "google-chrome.desktop#Profile 1"
+        );
+        assert_eq!(
+            config.get_rules()[0].get_rule().unwrap().profile.to_vec(),
+            vec!["firefox.desktop#work", "slack.desktop#T123"]
+        );
+        assert_eq!(
+            config.get_rules()[1]
+                .get_rule()
+                .unwrap()
+                .action
+                .as_ref()
+                .unwrap()
+                .get_profile_ids(),
+            vec!["firefox.desktop#work"]
+        );
+
+        // nothing left to migrate, but the used aliases are kept for ids added later
+        assert_eq!(config.migrate_legacy_ids(&IdAliases::new()), 0);
+        config.hide_profile("/usr/bin/google-chrome#Profile 1");
+        assert_eq!(config.migrate_legacy_ids(&IdAliases::new()), 1);
+        assert_eq!(
+            config.get_hidden_profiles(),
+            &vec![
+                "google-chrome.desktop#Default",
+                "google-chrome.desktop#Profile 1"
+            ]
+        );
+    }
+}
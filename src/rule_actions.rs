@@ -0,0 +1,276 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::ProfileAndOptions;
+
+const URL_PLACEHOLDER: &'static str = "{url}";
+
+// One profile id, or ids to try in order, e.g when a browser was reinstalled and its
+// profile ids changed: `"profile": ["new-id", "old-id"]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ProfileCandidates {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for ProfileCandidates {
+    fn default() -> Self {
+        return ProfileCandidates::One("".to_string());
+    }
+}
+
+impl ProfileCandidates {
+    pub fn to_vec(&self) -> Vec<String> {
+        return match self {
+            ProfileCandidates::One(profile_id) => vec![profile_id.clone()],
+            ProfileCandidates::Many(profile_ids) => profile_ids.clone(),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.to_vec().iter().all(|profile_id| profile_id.is_empty());
+    }
+
+    pub fn for_each_id_mut(&mut self, f: &mut impl FnMut(&mut String)) {
+        match self {
+            ProfileCandidates::One(profile_id) => f(profile_id),
+            ProfileCandidates::Many(profile_ids) => profile_ids.iter_mut().for_each(f),
+        }
+    }
+}
+
+impl fmt::Display for ProfileCandidates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_vec().join(" or "))
+    }
+}
+
+// What happens with a link when a rule matches it.
+// Rules with just `profile` (and `incognito`) open the link in that profile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    // {"type": "open_profile", "profile": "...", "incognito": true}
+    // {"type": "open_profile", "profile": ["...", "fallback"]}
+    OpenProfile {
+        profile: ProfileCandidates,
+        #[serde(default)]
+        incognito: bool,
+    },
+    // {"type": "open_profiles", "profiles": [{"profile": "..."}, {"profile": "...", "incognito": true}]}
+    OpenProfiles {
+        profiles: Vec<ProfileAndOptions>,
+    },
+    // {"type": "picker", "profiles": ["...", "..."]}
+    Picker {
+        profiles: Vec<String>,
+    },
+    // {"type": "copy_to_clipboard"}
+    CopyToClipboard,
+    // {"type": "block", "message": "Not on this machine"}
+    Block {
+        #[serde(default)]
+        message: Option<String>,
+    },
+    // {"type": "run_command", "command": ["mpv", "--fs", "{url}"]}
+    RunCommand {
+        command: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionError {
+    pub action: &'static str,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} action: {}", self.action, self.reason)
+    }
+}
+
+impl RuleAction {
+    pub fn from_profile_and_options(profile_and_options: &ProfileAndOptions) -> Self {
+        return RuleAction::OpenProfile {
+            profile: ProfileCandidates::One(profile_and_options.profile.clone()),
+            incognito: profile_and_options.incognito,
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            RuleAction::OpenProfile { .. } => "open_profile",
+            RuleAction::OpenProfiles { .. } => "open_profiles",
+            RuleAction::Picker { .. } => "picker",
+            RuleAction::CopyToClipboard => "copy_to_clipboard",
+            RuleAction::Block { .. } => "block",
+            RuleAction::RunCommand { .. } => "run_command",
+        };
+    }
+
+    // all profile ids the action refers to
+    pub fn get_profile_ids(&self) -> Vec<String> {
+        return match self {
+            RuleAction::OpenProfile { profile, .. } => profile.to_vec(),
+            RuleAction::OpenProfiles { profiles } => profiles
+                .iter()
+                .map(|profile_and_options| profile_and_options.profile.clone())
+                .collect(),
+            RuleAction::Picker { profiles } => profiles.clone(),
+            _ => vec![],
+        };
+    }
+
+    // e.g to replace legacy ids, see profile_ids
+    pub fn for_each_profile_id_mut(&mut self, f: &mut impl FnMut(&mut String)) {
+        match self {
+            RuleAction::OpenProfile { profile, .. } => profile.for_each_id_mut(f),
+            RuleAction::OpenProfiles { profiles } => profiles
+                .iter_mut()
+                .for_each(|profile_and_options| f(&mut profile_and_options.profile)),
+            RuleAction::Picker { profiles } => profiles.iter_mut().for_each(f),
+            _ => {}
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ActionError> {
+        let reason_maybe = match self {
+            RuleAction::OpenProfile {
+                profile: ProfileCandidates::Many(profiles),
+                ..
+            } if profiles.is_empty() => Some("profile must not be an empty list"),
+            RuleAction::OpenProfiles { profiles } if profiles.is_empty() => {
+                Some("profiles must not be empty")
+            }
+            RuleAction::Picker { profiles } if profiles.is_empty() => {
+                Some("profiles must not be empty")
+            }
+            RuleAction::RunCommand { command } if command.is_empty() => {
+                Some("command must not be empty")
+            }
+            RuleAction::RunCommand { command } if command[0].trim().is_empty() => {
+                Some("program must not be empty")
+            }
+            _ => None,
+        };
+
+        return match reason_maybe {
+            Some(reason) => Err(ActionError {
+                action: self.name(),
+                reason: reason,
+            }),
+            None => Ok(()),
+        };
+    }
+
+    // short human readable form, e.g for `browsers rules test`
+    pub fn description(&self) -> String {
+        return match self {
+            RuleAction::OpenProfile { profile, incognito } => {
+                format!("{}{}", profile, if *incognito { " (incognito)" } else { "" })
+            }
+            RuleAction::OpenProfiles { profiles } => {
+                let profile_descriptions: Vec<String> = profiles
+                    .iter()
+                    .map(|p| RuleAction::from_profile_and_options(p).description())
+                    .collect();
+                format!("all of {}", profile_descriptions.join(", "))
+            }
+            RuleAction::Picker { profiles } => format!("picker with {}", profiles.join(", ")),
+            RuleAction::CopyToClipboard => "copy to clipboard".to_string(),
+            RuleAction::Block { .. } => "block".to_string(),
+            RuleAction::RunCommand { command } => format!("run {}", command.join(" ")),
+        };
+    }
+}
+
+// replaces {url} in every argument; the url is appended if there is no {url} at all
+pub fn command_with_url(command: &[String], url: &str) -> Vec<String> {
+    let has_placeholder = command.iter().any(|arg| arg.contains(URL_PLACEHOLDER));
+
+    let mut command_with_url: Vec<String> = command
+        .iter()
+        .map(|arg| arg.replace(URL_PLACEHOLDER, url))
+        .collect();
+    if !has_placeholder {
+        command_with_url.push(url.to_string());
+    }
+    return command_with_url;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_actions() {
+        let actions: Vec<RuleAction> = serde_json::from_str(
+            r#"[
+                {"type": "open_profile", "profile": "work"},
+                {"type": "open_profile", "profile": ["new-work", "work"], "incognito": true},
+                {"type": "open_profiles", "profiles": [{"profile": "a"}, {"profile": "b", "incognito": true}]},
+                {"type": "picker", "profiles": ["a", "b"]},
+                {"type": "copy_to_clipboard"},
+                {"type": "block"},
+                {"type": "run_command", "command": ["mpv", "{url}"]}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            actions[0],
+            RuleAction::OpenProfile {
+                profile: ProfileCandidates::One("work".to_string()),
+                incognito: false,
+            }
+        );
+        assert_eq!(actions[1].description(), "new-work or work (incognito)");
+        assert_eq!(actions[2].description(), "all of a, b (incognito)");
+        assert_eq!(actions[3].description(), "picker with a, b");
+        assert_eq!(actions[4], RuleAction::CopyToClipboard);
+        assert_eq!(actions[5], RuleAction::Block { message: None });
+        assert_eq!(actions[6].description(), "run mpv {url}");
+
+        let unknown_result: Result<RuleAction, _> = serde_json::from_str(r#"{"type": "print"}"#);
+        assert_eq!(unknown_result.is_err(), true);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(RuleAction::CopyToClipboard.validate(), Ok(()));
+        assert_eq!(
+            RuleAction::Picker { profiles: vec![] }.validate(),
+            Err(ActionError {
+                action: "picker",
+                reason: "profiles must not be empty",
+            })
+        );
+        assert_eq!(
+            RuleAction::RunCommand {
+                command: vec!["".to_string()]
+            }
+            .validate()
+            .unwrap_err()
+            .to_string(),
+            "invalid run_command action: program must not be empty"
+        );
+    }
+
+    #[test]
+    fn test_command_with_url() {
+        let command = vec!["mpv".to_string(), "--url={url}".to_string()];
+        assert_eq!(
+            command_with_url(&command, "https://example.com/a b"),
+            vec!["mpv", "--url=https://example.com/a b"]
+        );
+
+        let command = vec!["yt-dlp".to_string()];
+        assert_eq!(
+            command_with_url(&command, "https://example.com/"),
+            vec!["yt-dlp", "https://example.com/"]
+        );
+    }
+}
@@ -0,0 +1,760 @@
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, Time, Weekday};
+use time_tz::{timezones, OffsetDateTimeExt, Tz};
+use tracing::warn;
+
+use crate::utils;
+
+// Invalid condition of a rule (other than url pattern) in config.json
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionError {
+    pub field: &'static str,
+    pub value: String,
+    pub reason: String,
+}
+
+impl ConditionError {
+    fn new(field: &'static str, value: &str, reason: &str) -> Self {
+        return Self {
+            field: field,
+            value: value.to_string(),
+            reason: reason.to_string(),
+        };
+    }
+}
+
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} \"{}\": {}", self.field, self.value, self.reason)
+    }
+}
+
+impl Error for ConditionError {}
+
+// Source of current time, so that tests can pin it
+pub trait Clock {
+    fn now(&self) -> OffsetDateTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        return OffsetDateTime::now_utc();
+    }
+}
+
+// Source of hostname and environment variables, so that tests can fake them
+pub trait Environment {
+    fn hostname(&self) -> Option<String>;
+    fn var(&self, key: &str) -> Option<String>;
+}
+
+pub struct SystemEnvironment;
+
+impl Environment for SystemEnvironment {
+    fn hostname(&self) -> Option<String> {
+        return utils::get_hostname();
+    }
+
+    fn var(&self, key: &str) -> Option<String> {
+        return env::var(key).ok();
+    }
+}
+
+// Rule applies only at given times, e.g in config.json:
+// "schedule": { "weekdays": ["mon", "tue", "wed", "thu", "fri"], "from": "09:00", "to": "18:00" }
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConfigSchedule {
+    // "mon" or "monday"; empty means every day.
+    // For a window over midnight, the day the window starts, e.g "fri" from 22:00 to 06:00
+    // includes saturday night until 06:00
+    pub weekdays: Vec<String>,
+    // "HH:MM", start of the time window (inclusive); start of the day if missing
+    pub from: Option<String>,
+    // "HH:MM", end of the time window (exclusive); end of the day if missing.
+    // Can be before "from" for a window over midnight, e.g from 22:00 to 06:00
+    pub to: Option<String>,
+    // e.g "Europe/Tallinn"; timezone of the computer if missing
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    weekdays: Vec<Weekday>,
+    from: Time,
+    // None is end of the day
+    to: Option<Time>,
+    timezone: &'static Tz,
+}
+
+impl Schedule {
+    pub fn from_config_schedule(config_schedule: &ConfigSchedule) -> Result<Self, ConditionError> {
+        let mut weekdays: Vec<Weekday> = Vec::new();
+        for weekday_str in &config_schedule.weekdays {
+            let weekday = parse_weekday(weekday_str.as_str()).ok_or_else(|| {
+                ConditionError::new(
+                    "schedule weekday",
+                    weekday_str.as_str(),
+                    "expected mon, tue, wed, thu, fri, sat or sun",
+                )
+            })?;
+            weekdays.push(weekday);
+        }
+
+        let from = match config_schedule.from {
+            Some(ref from_str) => parse_time_of_day(from_str.as_str())
+                .map_err(|reason| ConditionError::new("schedule from", from_str.as_str(), reason))?
+                .ok_or_else(|| {
+                    // 24:00 is only meaningful as the end of a window
+                    ConditionError::new(
+                        "schedule from",
+                        from_str.as_str(),
+                        "24:00 can only be used in \"to\", use 00:00 for the start of the day",
+                    )
+                })?,
+            None => Time::MIDNIGHT,
+        };
+
+        let to = match config_schedule.to {
+            Some(ref to_str) => parse_time_of_day(to_str.as_str())
+                .map_err(|reason| ConditionError::new("schedule to", to_str.as_str(), reason))?,
+            None => None,
+        };
+
+        let timezone = match config_schedule.timezone {
+            Some(ref timezone_name) => {
+                timezones::get_by_name(timezone_name.as_str()).ok_or_else(|| {
+                    ConditionError::new(
+                        "schedule timezone",
+                        timezone_name.as_str(),
+                        "unknown timezone, expected a name like Europe/Tallinn",
+                    )
+                })?
+            }
+            None => get_local_timezone(),
+        };
+
+        return Ok(Self {
+            weekdays: weekdays,
+            from: from,
+            to: to,
+            timezone: timezone,
+        });
+    }
+
+    pub fn is_active(&self, now: &OffsetDateTime) -> bool {
+        let local_now = now.to_timezone(self.timezone);
+        let time = local_now.time();
+
+        // weekdays are matched with the day the window started
+        let (is_in_window, window_weekday) = match self.to {
+            None => (time >= self.from, local_now.weekday()),
+            Some(to) if self.from <= to => (time >= self.from && time < to, local_now.weekday()),
+            // over midnight, before midnight
+            Some(_) if time >= self.from => (true, local_now.weekday()),
+            // over midnight, after midnight, so the window started the day before
+            Some(to) => (time < to, local_now.weekday().previous()),
+        };
+        if !is_in_window {
+            return false;
+        }
+
+        return self.weekdays.is_empty() || self.weekdays.contains(&window_weekday);
+    }
+}
+
+// Rule applies only on matching machines or sessions, e.g in config.json:
+// "environment": { "hostname": "work-*", "env": { "BROWSERS_CONTEXT": "client-a" }, "session_type": "wayland" }
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConfigEnvironment {
+    // glob, e.g "work-laptop-*"
+    pub hostname: Option<String>,
+    pub env: BTreeMap<String, EnvVarCondition>,
+    // glob for $XDG_SESSION_TYPE, e.g "wayland" or "x11"
+    pub session_type: Option<String>,
+    // glob for any of the desktops in $XDG_CURRENT_DESKTOP, e.g "GNOME" or "KDE"
+    pub desktop: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EnvVarCondition {
+    // true: variable must be set (to any value), false: variable must not be set
+    Set(bool),
+    // variable must be set and its value must match the glob
+    Value(String),
+}
+
+#[derive(Debug, Clone)]
+enum EnvVarPredicate {
+    Set,
+    NotSet,
+    Value(GlobMatcher),
+}
+
+// Result of checking one condition, for explaining why a rule did (not) match
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionMatch {
+    // e.g "hostname" or "env BROWSERS_CONTEXT"
+    pub condition: String,
+    pub matches: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvironmentConditions {
+    hostname: Option<GlobMatcher>,
+    env: Vec<(String, EnvVarPredicate)>,
+    session_type: Option<GlobMatcher>,
+    desktop: Option<GlobMatcher>,
+}
+
+impl EnvironmentConditions {
+    pub fn from_config_environment(
+        config_environment: &ConfigEnvironment,
+    ) -> Result<Self, ConditionError> {
+        let hostname = match config_environment.hostname {
+            Some(ref hostname_pattern) => Some(to_glob_matcher(
+                "environment hostname",
+                hostname_pattern.as_str(),
+                true,
+            )?),
+            None => None,
+        };
+
+        let mut env_predicates: Vec<(String, EnvVarPredicate)> = Vec::new();
+        for (key, condition) in &config_environment.env {
+            let predicate =
+                match condition {
+                    EnvVarCondition::Set(true) => EnvVarPredicate::Set,
+                    EnvVarCondition::Set(false) => EnvVarPredicate::NotSet,
+                    // values of environment variables are case-sensitive
+                    EnvVarCondition::Value(value_pattern) => EnvVarPredicate::Value(
+                        to_glob_matcher("environment env", value_pattern.as_str(), false)?,
+                    ),
+                };
+            env_predicates.push((key.clone(), predicate));
+        }
+
+        let session_type = match config_environment.session_type {
+            Some(ref session_type_pattern) => Some(to_glob_matcher(
+                "environment session_type",
+                session_type_pattern.as_str(),
+                true,
+            )?),
+            None => None,
+        };
+
+        let desktop = match config_environment.desktop {
+            Some(ref desktop_pattern) => Some(to_glob_matcher(
+                "environment desktop",
+                desktop_pattern.as_str(),
+                true,
+            )?),
+            None => None,
+        };
+
+        return Ok(Self {
+            hostname: hostname,
+            env: env_predicates,
+            session_type: session_type,
+            desktop: desktop,
+        });
+    }
+
+    pub fn conditions_count(&self) -> usize {
+        return self.hostname.iter().count()
+            + self.env.len()
+            + self.session_type.iter().count()
+            + self.desktop.iter().count();
+    }
+
+    // checks every condition, all of them must match for the rule to match
+    pub fn explain_match(&self, environment: &dyn Environment) -> Vec<ConditionMatch> {
+        let mut condition_matches: Vec<ConditionMatch> = Vec::new();
+
+        if let Some(ref hostname_matcher) = self.hostname {
+            let matches = environment
+                .hostname()
+                .map_or(false, |hostname| hostname_matcher.is_match(hostname));
+            condition_matches.push(ConditionMatch {
+                condition: "hostname".to_string(),
+                matches: matches,
+            });
+        }
+
+        for (key, predicate) in &self.env {
+            let value_maybe = environment.var(key.as_str());
+            let matches = match predicate {
+                EnvVarPredicate::Set => value_maybe.is_some(),
+                EnvVarPredicate::NotSet => value_maybe.is_none(),
+                EnvVarPredicate::Value(value_matcher) => {
+                    value_maybe.map_or(false, |value| value_matcher.is_match(value))
+                }
+            };
+            condition_matches.push(ConditionMatch {
+                condition: format!("env {}", key),
+                matches: matches,
+            });
+        }
+
+        if let Some(ref session_type_matcher) = self.session_type {
+            let matches = environment
+                .var("XDG_SESSION_TYPE")
+                .map_or(false, |session_type| session_type_matcher.is_match(session_type));
+            condition_matches.push(ConditionMatch {
+                condition: "session_type".to_string(),
+                matches: matches,
+            });
+        }
+
+        if let Some(ref desktop_matcher) = self.desktop {
+            // e.g "ubuntu:GNOME"
+            let matches = environment
+                .var("XDG_CURRENT_DESKTOP")
+                .map_or(false, |desktops| {
+                    desktops
+                        .split(':')
+                        .any(|desktop| desktop_matcher.is_match(desktop))
+                });
+            condition_matches.push(ConditionMatch {
+                condition: "desktop".to_string(),
+                matches: matches,
+            });
+        }
+
+        return condition_matches;
+    }
+}
+
+// App which opened the link, e.g in config.json:
+// "source_app": "com.tinyspeck.slackmacgap" or "source_app": ["slack*.desktop", "org.telegram.*"]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SourceAppPatterns {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl SourceAppPatterns {
+    pub fn to_vec(&self) -> Vec<String> {
+        return match self {
+            SourceAppPatterns::One(pattern) => vec![pattern.clone()],
+            SourceAppPatterns::Many(patterns) => patterns.clone(),
+        };
+    }
+}
+
+impl fmt::Display for SourceAppPatterns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_vec().join(","))
+    }
+}
+
+// Source app of the link, which is detected only when a rule has a source app condition,
+// as detecting it can be slow (e.g on Linux it walks /proc and reads .desktop files)
+pub struct LazySourceApp {
+    source_app: OnceCell<Option<String>>,
+    detect: Box<dyn Fn() -> Option<String>>,
+}
+
+impl LazySourceApp {
+    pub fn known(source_app_maybe: Option<String>) -> Self {
+        return Self {
+            source_app: OnceCell::from(source_app_maybe),
+            detect: Box::new(|| None),
+        };
+    }
+
+    pub fn detected_with(detect: impl Fn() -> Option<String> + 'static) -> Self {
+        return Self {
+            source_app: OnceCell::new(),
+            detect: Box::new(detect),
+        };
+    }
+
+    pub fn get(&self) -> Option<&String> {
+        return self.source_app.get_or_init(|| (self.detect)()).as_ref();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceAppMatcher {
+    globs: Vec<GlobMatcher>,
+}
+
+impl SourceAppMatcher {
+    pub fn from_patterns(
+        field: &'static str,
+        source_app_patterns: &SourceAppPatterns,
+    ) -> Result<Self, ConditionError> {
+        let patterns = source_app_patterns.to_vec();
+        if patterns.is_empty() {
+            return Err(ConditionError::new(
+                field,
+                "[]",
+                "list must have at least one app",
+            ));
+        }
+
+        let mut globs: Vec<GlobMatcher> = Vec::new();
+        for pattern in patterns {
+            globs.push(to_glob_matcher(field, pattern.as_str(), true)?);
+        }
+
+        return Ok(Self { globs: globs });
+    }
+
+    // true if any of the patterns matches
+    pub fn is_match(&self, source_app: &str) -> bool {
+        return self.globs.iter().any(|glob| glob.is_match(source_app));
+    }
+}
+
+fn to_glob_matcher(
+    field: &'static str,
+    pattern: &str,
+    case_insensitive: bool,
+) -> Result<GlobMatcher, ConditionError> {
+    let glob_result = GlobBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build();
+    if glob_result.is_err() {
+        let glob_error = glob_result.unwrap_err();
+        return Err(ConditionError::new(
+            field,
+            pattern,
+            glob_error.kind().to_string().as_str(),
+        ));
+    }
+    return Ok(glob_result.unwrap().compile_matcher());
+}
+
+fn get_local_timezone() -> &'static Tz {
+    let timezone_result = time_tz::system::get_timezone();
+    if timezone_result.is_err() {
+        warn!(
+            "Could not detect local timezone, using UTC for rule schedules: {}",
+            timezone_result.unwrap_err()
+        );
+        return time_tz::timezones::db::UTC;
+    }
+    return timezone_result.unwrap();
+}
+
+fn parse_weekday(weekday_str: &str) -> Option<Weekday> {
+    return match weekday_str.to_lowercase().as_str() {
+        "mon" | "monday" => Some(Weekday::Monday),
+        "tue" | "tuesday" => Some(Weekday::Tuesday),
+        "wed" | "wednesday" => Some(Weekday::Wednesday),
+        "thu" | "thursday" => Some(Weekday::Thursday),
+        "fri" | "friday" => Some(Weekday::Friday),
+        "sat" | "saturday" => Some(Weekday::Saturday),
+        "sun" | "sunday" => Some(Weekday::Sunday),
+        _ => None,
+    };
+}
+
+// "09:30" -> 09:30; "24:00" -> None (end of the day)
+fn parse_time_of_day(time_str: &str) -> Result<Option<Time>, &'static str> {
+    let (hour_str, minute_str) = time_str.split_once(':').ok_or("expected HH:MM")?;
+    let hour = u8::from_str(hour_str).map_err(|_| "expected HH:MM")?;
+    let minute = u8::from_str(minute_str).map_err(|_| "expected HH:MM")?;
+
+    if hour == 24 && minute == 0 {
+        return Ok(None);
+    }
+
+    let time = Time::from_hms(hour, minute, 0).map_err(|_| "hour or minute out of range")?;
+    return Ok(Some(time));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    struct FakeEnvironment {
+        hostname: Option<String>,
+        vars: HashMap<String, String>,
+    }
+
+    impl Environment for FakeEnvironment {
+        fn hostname(&self) -> Option<String> {
+            return self.hostname.clone();
+        }
+
+        fn var(&self, key: &str) -> Option<String> {
+            return self.vars.get(key).cloned();
+        }
+    }
+
+    fn fake_environment(hostname: &str, vars: &[(&str, &str)]) -> FakeEnvironment {
+        return FakeEnvironment {
+            hostname: Some(hostname.to_string()),
+            vars: vars
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        };
+    }
+
+    fn work_hours() -> Schedule {
+        return Schedule::from_config_schedule(&ConfigSchedule {
+            weekdays: vec![
+                "mon".to_string(),
+                "tue".to_string(),
+                "wed".to_string(),
+                "thu".to_string(),
+                "Friday".to_string(),
+            ],
+            from: Some("09:00".to_string()),
+            to: Some("18:00".to_string()),
+            timezone: Some("Europe/Tallinn".to_string()),
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_schedule_is_active_in_time_window_on_weekdays() {
+        let schedule = work_hours();
+
+        // Monday 10:00 in Tallinn (UTC+3 in summer)
+        assert_eq!(schedule.is_active(&datetime!(2023-08-14 07:00 UTC)), true);
+        // Monday 08:59 in Tallinn
+        assert_eq!(schedule.is_active(&datetime!(2023-08-14 05:59 UTC)), false);
+        // Monday 18:00 in Tallinn, end is exclusive
+        assert_eq!(schedule.is_active(&datetime!(2023-08-14 15:00 UTC)), false);
+        // Saturday 10:00 in Tallinn
+        assert_eq!(schedule.is_active(&datetime!(2023-08-19 07:00 UTC)), false);
+        // Monday 10:00 in Tallinn in winter (UTC+2)
+        assert_eq!(schedule.is_active(&datetime!(2023-12-04 08:00 UTC)), true);
+    }
+
+    #[test]
+    fn test_schedule_over_midnight() {
+        let schedule = Schedule::from_config_schedule(&ConfigSchedule {
+            from: Some("22:00".to_string()),
+            to: Some("06:00".to_string()),
+            timezone: Some("UTC".to_string()),
+            ..ConfigSchedule::default()
+        })
+        .unwrap();
+
+        assert_eq!(schedule.is_active(&datetime!(2023-08-14 23:30 UTC)), true);
+        assert_eq!(schedule.is_active(&datetime!(2023-08-14 05:59 UTC)), true);
+        assert_eq!(schedule.is_active(&datetime!(2023-08-14 12:00 UTC)), false);
+
+        let friday_night = Schedule::from_config_schedule(&ConfigSchedule {
+            weekdays: vec!["fri".to_string()],
+            from: Some("22:00".to_string()),
+            to: Some("06:00".to_string()),
+            timezone: Some("UTC".to_string()),
+            ..ConfigSchedule::default()
+        })
+        .unwrap();
+
+        // Friday 23:30
+        assert_eq!(friday_night.is_active(&datetime!(2023-08-18 23:30 UTC)), true);
+        // Saturday 02:00, the window started on Friday
+        assert_eq!(friday_night.is_active(&datetime!(2023-08-19 02:00 UTC)), true);
+        // Friday 02:00, the window started on Thursday
+        assert_eq!(friday_night.is_active(&datetime!(2023-08-18 02:00 UTC)), false);
+        // Saturday 23:00
+        assert_eq!(friday_night.is_active(&datetime!(2023-08-19 23:00 UTC)), false);
+    }
+
+    #[test]
+    fn test_schedule_only_weekdays() {
+        let schedule = Schedule::from_config_schedule(&ConfigSchedule {
+            weekdays: vec!["sat".to_string(), "sun".to_string()],
+            to: Some("24:00".to_string()),
+            timezone: Some("America/New_York".to_string()),
+            ..ConfigSchedule::default()
+        })
+        .unwrap();
+
+        // Saturday 01:00 UTC is still Friday in New York
+        assert_eq!(schedule.is_active(&datetime!(2023-08-19 01:00 UTC)), false);
+        assert_eq!(schedule.is_active(&datetime!(2023-08-19 23:59 UTC)), true);
+    }
+
+    #[test]
+    fn test_schedule_reports_invalid_values() {
+        let error = Schedule::from_config_schedule(&ConfigSchedule {
+            weekdays: vec!["funday".to_string()],
+            ..ConfigSchedule::default()
+        })
+        .unwrap_err();
+        assert_eq!(error.field, "schedule weekday");
+
+        let error = Schedule::from_config_schedule(&ConfigSchedule {
+            from: Some("9am".to_string()),
+            ..ConfigSchedule::default()
+        })
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid schedule from \"9am\": expected HH:MM"
+        );
+
+        let error = Schedule::from_config_schedule(&ConfigSchedule {
+            from: Some("24:00".to_string()),
+            ..ConfigSchedule::default()
+        })
+        .unwrap_err();
+        assert_eq!(error.field, "schedule from");
+
+        let error = Schedule::from_config_schedule(&ConfigSchedule {
+            timezone: Some("Mars/Olympus".to_string()),
+            ..ConfigSchedule::default()
+        })
+        .unwrap_err();
+        assert_eq!(error.field, "schedule timezone");
+    }
+
+    #[test]
+    fn test_environment_conditions_match() {
+        let config_environment: ConfigEnvironment = serde_json::from_str(
+            r#"{
+                "hostname": "work-*",
+                "env": { "BROWSERS_CONTEXT": "client-a", "SSH_CONNECTION": false },
+                "session_type": "wayland",
+                "desktop": "gnome"
+            }"#,
+        )
+        .unwrap();
+        let environment_conditions =
+            EnvironmentConditions::from_config_environment(&config_environment).unwrap();
+        assert_eq!(environment_conditions.conditions_count(), 5);
+
+        let work_laptop = fake_environment(
+            "WORK-laptop-1",
+            &[
+                ("BROWSERS_CONTEXT", "client-a"),
+                ("XDG_SESSION_TYPE", "wayland"),
+                ("XDG_CURRENT_DESKTOP", "ubuntu:GNOME"),
+            ],
+        );
+        let condition_matches = environment_conditions.explain_match(&work_laptop);
+        assert_eq!(
+            condition_matches
+                .iter()
+                .map(|condition_match| (
+                    condition_match.condition.as_str(),
+                    condition_match.matches
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("hostname", true),
+                ("env BROWSERS_CONTEXT", true),
+                ("env SSH_CONNECTION", true),
+                ("session_type", true),
+                ("desktop", true),
+            ]
+        );
+
+        let shared_workstation = fake_environment(
+            "shared-workstation",
+            &[
+                ("BROWSERS_CONTEXT", "Client-A"),
+                ("SSH_CONNECTION", "10.0.0.1 22 10.0.0.2 22"),
+                ("XDG_SESSION_TYPE", "x11"),
+                ("XDG_CURRENT_DESKTOP", "KDE"),
+            ],
+        );
+        let condition_matches = environment_conditions.explain_match(&shared_workstation);
+        assert_eq!(
+            condition_matches
+                .iter()
+                .all(|condition_match| !condition_match.matches),
+            true
+        );
+    }
+
+    #[test]
+    fn test_environment_conditions_without_hostname_or_variables() {
+        let environment_conditions =
+            EnvironmentConditions::from_config_environment(&ConfigEnvironment {
+                hostname: Some("*".to_string()),
+                env: BTreeMap::from([("BROWSERS_CONTEXT".to_string(), EnvVarCondition::Set(true))]),
+                ..ConfigEnvironment::default()
+            })
+            .unwrap();
+
+        let unknown = FakeEnvironment {
+            hostname: None,
+            vars: HashMap::new(),
+        };
+        assert_eq!(
+            environment_conditions.explain_match(&unknown),
+            vec![
+                ConditionMatch {
+                    condition: "hostname".to_string(),
+                    matches: false,
+                },
+                ConditionMatch {
+                    condition: "env BROWSERS_CONTEXT".to_string(),
+                    matches: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_environment_conditions_report_invalid_glob() {
+        let error = EnvironmentConditions::from_config_environment(&ConfigEnvironment {
+            hostname: Some("work-[".to_string()),
+            ..ConfigEnvironment::default()
+        })
+        .unwrap_err();
+        assert_eq!(error.field, "environment hostname");
+        assert_eq!(error.value, "work-[");
+    }
+
+    #[test]
+    fn test_source_app_matcher_globs_and_lists() {
+        let chat_apps: SourceAppPatterns =
+            serde_json::from_str(r#"["slack*.desktop", "org.telegram.*"]"#).unwrap();
+        let matcher = SourceAppMatcher::from_patterns("source_app", &chat_apps).unwrap();
+
+        assert_eq!(matcher.is_match("slack.desktop"), true);
+        assert_eq!(matcher.is_match("Slack_Beta.desktop"), true);
+        assert_eq!(matcher.is_match("org.telegram.desktop"), true);
+        assert_eq!(matcher.is_match("firefox.desktop"), false);
+
+        let single: SourceAppPatterns =
+            serde_json::from_str(r#""com.tinyspeck.slackmacgap""#).unwrap();
+        let matcher = SourceAppMatcher::from_patterns("source_app", &single).unwrap();
+        assert_eq!(matcher.is_match("com.tinyspeck.slackmacgap"), true);
+        assert_eq!(matcher.is_match("com.tinyspeck.slackmacgap.helper"), false);
+    }
+
+    #[test]
+    fn test_source_app_matcher_reports_invalid_patterns() {
+        let error =
+            SourceAppMatcher::from_patterns("not_source_app", &SourceAppPatterns::Many(vec![]))
+                .unwrap_err();
+        assert_eq!(error.field, "not_source_app");
+
+        let error = SourceAppMatcher::from_patterns(
+            "source_app",
+            &SourceAppPatterns::One("slack[".to_string()),
+        )
+        .unwrap_err();
+        assert_eq!(error.value, "slack[");
+    }
+}
@@ -0,0 +1,423 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::profile_ids;
+use crate::utils::{Config, ConfigRuleEntry};
+
+// A settings bundle is a config file with only the settings which are moved between machines
+// (hidden apps and profiles, profile order, default profile, rules and ui), written by
+// `browsers config export` and read by `browsers config import`.
+// Ids in it don't have executable paths, so they match the same apps on another machine.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    // settings from the bundle are added, but local values are kept when both are set
+    Merge,
+    // settings in the bundle replace local settings
+    Replace,
+}
+
+// setting which has a different value in the bundle and in local config
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportConflict {
+    // e.g "default_profile" or "rule #3"
+    pub setting: String,
+    pub local_value: String,
+    pub imported_value: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub conflicts: Vec<ImportConflict>,
+    pub added_rules: usize,
+    pub added_hidden: usize,
+    // apps in the bundle which are not installed on this machine
+    pub missing_apps: Vec<String>,
+}
+
+impl ImportReport {
+    fn new(mode: ImportMode) -> Self {
+        return Self {
+            mode: mode,
+            conflicts: vec![],
+            added_rules: 0,
+            added_hidden: 0,
+            missing_apps: vec![],
+        };
+    }
+
+    // human readable summary, one change or conflict per line
+    pub fn summary_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for conflict in &self.conflicts {
+            lines.push(match self.mode {
+                ImportMode::Merge => format!(
+                    "{}: kept {}, bundle has {} (use --replace to use it)",
+                    conflict.setting, conflict.local_value, conflict.imported_value
+                ),
+                ImportMode::Replace => format!(
+                    "{}: replaced {} with {}",
+                    conflict.setting, conflict.local_value, conflict.imported_value
+                ),
+            });
+        }
+        for missing_app in &self.missing_apps {
+            lines.push(format!(
+                "{} is not installed, its settings are kept for when it is",
+                missing_app
+            ));
+        }
+        if self.mode == ImportMode::Merge {
+            lines.push(format!(
+                "added {} rules and {} hidden apps and profiles",
+                self.added_rules, self.added_hidden
+            ));
+        }
+        return lines;
+    }
+}
+
+// Copy of the settings to export, with ids which work on other machines
+pub fn export_settings(config: &Config) -> Config {
+    let mut bundle = config.get_exported_settings();
+    bundle.for_each_profile_id_mut(&mut |id| *id = profile_ids::portable_id(id));
+    return bundle;
+}
+
+// Applies settings from a bundle to config.
+// Ids in the bundle are changed to ids of apps installed on this machine (app_ids).
+pub fn import_settings(
+    config: &mut Config,
+    bundle: &Config,
+    app_ids: &[String],
+    mode: ImportMode,
+) -> ImportReport {
+    let mut report = ImportReport::new(mode);
+    let mut bundle = bundle.get_exported_settings();
+    bundle.for_each_profile_id_mut(&mut |id| {
+        // rules with only an action have no profile
+        if id.is_empty() {
+            return;
+        }
+        *id = profile_ids::local_id(id, app_ids);
+
+        let (app_id, _) = profile_ids::split_app_id(id);
+        let is_installed = app_ids
+            .iter()
+            .any(|installed_app_id| installed_app_id == app_id);
+        if !is_installed && !report.missing_apps.iter().any(|missing| missing == app_id) {
+            report.missing_apps.push(app_id.to_string());
+        }
+    });
+
+    let mut hidden_apps = config.get_hidden_apps().clone();
+    let mut hidden_profiles = config.get_hidden_profiles().clone();
+    match mode {
+        ImportMode::Merge => {
+            report.added_hidden += merge_ids(&mut hidden_apps, bundle.get_hidden_apps());
+            report.added_hidden += merge_ids(&mut hidden_profiles, bundle.get_hidden_profiles());
+        }
+        ImportMode::Replace => {
+            hidden_apps = import_value(
+                "hidden_apps",
+                &hidden_apps,
+                bundle.get_hidden_apps(),
+                mode,
+                &mut report,
+            );
+            hidden_profiles = import_value(
+                "hidden_profiles",
+                &hidden_profiles,
+                bundle.get_hidden_profiles(),
+                mode,
+                &mut report,
+            );
+        }
+    }
+    config.set_hidden_apps(hidden_apps);
+    config.set_hidden_profiles(hidden_profiles);
+
+    let profile_order = import_value(
+        "profile_order",
+        config.get_profile_order(),
+        bundle.get_profile_order(),
+        mode,
+        &mut report,
+    );
+    config.set_profile_order(&profile_order);
+
+    let default_profile = import_value(
+        "default_profile",
+        config.get_default_profile(),
+        bundle.get_default_profile(),
+        mode,
+        &mut report,
+    );
+    config.set_default_profile(default_profile);
+
+    let ui_config = import_value(
+        "ui",
+        config.get_ui_config(),
+        bundle.get_ui_config(),
+        mode,
+        &mut report,
+    );
+    config.set_ui_config(ui_config);
+
+    let rules = import_rules(config.get_rules(), bundle.get_rules(), mode, &mut report);
+    config.set_rules(rules);
+
+    return report;
+}
+
+// adds ids which are not in local yet, returns how many were added
+fn merge_ids(local: &mut Vec<String>, imported: &Vec<String>) -> usize {
+    let local_count = local.len();
+    for id in imported {
+        if !local.contains(id) {
+            local.push(id.clone());
+        }
+    }
+    return local.len() - local_count;
+}
+
+// Local value which is not set (default) is never a conflict,
+// and when merging, neither is a value which is not set in the bundle.
+fn import_value<T: Clone + Default + PartialEq + Serialize>(
+    setting: &str,
+    local: &T,
+    imported: &T,
+    mode: ImportMode,
+    report: &mut ImportReport,
+) -> T {
+    let default = T::default();
+    if local == imported || *local == default {
+        return imported.clone();
+    }
+    if *imported == default && mode == ImportMode::Merge {
+        return local.clone();
+    }
+
+    report.conflicts.push(ImportConflict {
+        setting: setting.to_string(),
+        local_value: to_json(local),
+        imported_value: to_json(imported),
+    });
+    return match mode {
+        ImportMode::Merge => local.clone(),
+        ImportMode::Replace => imported.clone(),
+    };
+}
+
+// Merging adds rules which are not in local config yet. A rule with the same conditions
+// as a local rule, but another profile or action is a conflict, and the local rule is kept.
+fn import_rules(
+    local_rules: &Vec<ConfigRuleEntry>,
+    imported_rules: &Vec<ConfigRuleEntry>,
+    mode: ImportMode,
+    report: &mut ImportReport,
+) -> Vec<ConfigRuleEntry> {
+    if mode == ImportMode::Replace {
+        if !local_rules.is_empty() && to_json(local_rules) != to_json(imported_rules) {
+            report.conflicts.push(ImportConflict {
+                setting: "rules".to_string(),
+                local_value: format!("{} rules", local_rules.len()),
+                imported_value: format!("{} rules", imported_rules.len()),
+            });
+        }
+        return imported_rules.clone();
+    }
+
+    let mut merged_rules = local_rules.clone();
+    for imported_rule in imported_rules {
+        let imported_rule_json = to_json(imported_rule);
+        if local_rules
+            .iter()
+            .any(|local_rule| to_json(local_rule) == imported_rule_json)
+        {
+            continue;
+        }
+
+        let imported_conditions = rule_conditions(imported_rule);
+        let same_conditions_index_maybe = local_rules
+            .iter()
+            .position(|local_rule| rule_conditions(local_rule) == imported_conditions);
+        if let Some(index) = same_conditions_index_maybe {
+            report.conflicts.push(ImportConflict {
+                setting: format!("rule #{}", index + 1),
+                local_value: rule_target(&local_rules[index]),
+                imported_value: rule_target(imported_rule),
+            });
+            continue;
+        }
+
+        merged_rules.push(imported_rule.clone());
+        report.added_rules += 1;
+    }
+    return merged_rules;
+}
+
+// rule without what it opens, to find rules for the same links
+fn rule_conditions(rule: &ConfigRuleEntry) -> Value {
+    let mut rule_json = serde_json::to_value(rule).unwrap_or_default();
+    if let Some(rule_object) = rule_json.as_object_mut() {
+        for target_key in ["profile", "incognito", "action"] {
+            rule_object.remove(target_key);
+        }
+    }
+    return rule_json;
+}
+
+fn rule_target(rule_entry: &ConfigRuleEntry) -> String {
+    return match rule_entry {
+        ConfigRuleEntry::Valid(rule) => match rule.action {
+            Some(ref action) => action.description(),
+            None => format!("{} (incognito={})", rule.profile, rule.incognito),
+        },
+        // rule which can't be read is shown as it is in config
+        ConfigRuleEntry::Invalid(value) => to_json(value),
+    };
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    return serde_json::to_string(value).unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(config_json: &str) -> Config {
+        return serde_json::from_str(config_json).unwrap();
+    }
+
+    #[test]
+    fn test_export_settings() {
+        let local_config = config(
+            r#"{
+                "hidden_profiles": ["org.mozilla.firefox@/Applications/Firefox.app#default"],
+                "rule_matching": "most_specific",
+                "legacy_id_aliases": {"/Applications/Firefox.app": "org.mozilla.firefox@/Applications/Firefox.app"},
+                "rules": [{"url_pattern": "github.com", "profile": "com.google.Chrome#Default"}]
+            }"#,
+        );
+
+        let bundle = serde_json::to_value(export_settings(&local_config)).unwrap();
+        assert_eq!(
+            bundle["hidden_profiles"],
+            serde_json::json!(["org.mozilla.firefox#default"])
+        );
+        assert_eq!(bundle["rules"][0]["profile"], "com.google.Chrome#Default");
+        assert_eq!(bundle.get("rule_matching"), None);
+        assert_eq!(bundle.get("legacy_id_aliases"), None);
+    }
+
+    #[test]
+    fn test_import_settings_merge() {
+        let mut local_config = config(
+            r#"{
+                "hidden_apps": ["epiphany.desktop"],
+                "default_profile": {"profile": "firefox.desktop#default"},
+                "rules": [{"url_pattern": "github.com", "profile": "firefox.desktop#work"}]
+            }"#,
+        );
+        let bundle = config(
+            r#"{
+                "hidden_apps": ["epiphany.desktop", "org.mozilla.firefox"],
+                "hidden_profiles": ["slack.desktop#T123"],
+                "default_profile": {"profile": "google-chrome.desktop#Default"},
+                "rules": [
+                    {"url_pattern": "github.com", "profile": "google-chrome.desktop#Default"},
+                    {"url_pattern": "*.corp.example", "profile": "google-chrome.desktop#Default"},
+                    {"url_pattern": "*.invalid", "action": {"type": "block"}}
+                ]
+            }"#,
+        );
+        let app_ids = vec![
+            "firefox.desktop".to_string(),
+            "google-chrome.desktop".to_string(),
+            "org.mozilla.firefox@/opt/firefox/firefox".to_string(),
+            "org.mozilla.firefox@/usr/bin/firefox".to_string(),
+        ];
+
+        let report = import_settings(&mut local_config, &bundle, &app_ids, ImportMode::Merge);
+        assert_eq!(
+            local_config.get_hidden_apps(),
+            &vec![
+                "epiphany.desktop",
+                "org.mozilla.firefox@/opt/firefox/firefox"
+            ]
+        );
+        assert_eq!(local_config.get_hidden_profiles(), &vec!["slack.desktop#T123"]);
+        assert_eq!(
+            local_config.get_default_profile().as_ref().unwrap().profile,
+            "firefox.desktop#default"
+        );
+        assert_eq!(local_config.get_rules().len(), 3);
+        assert_eq!(
+            local_config.get_rules()[1].get_rule().unwrap().url_pattern,
+            Some("*.corp.example".to_string())
+        );
+
+        assert_eq!(report.added_rules, 2);
+        assert_eq!(report.added_hidden, 2);
+        assert_eq!(report.missing_apps, vec!["epiphany.desktop", "slack.desktop"]);
+        assert_eq!(
+            report
+                .summary_lines()
+                .iter()
+                .any(|line| line.starts_with(" is not installed")),
+            false
+        );
+        assert_eq!(
+            report.conflicts,
+            vec![
+                ImportConflict {
+                    setting: "default_profile".to_string(),
+                    local_value: r#"{"profile":"firefox.desktop#default","incognito":false}"#
+                        .to_string(),
+                    imported_value:
+                        r#"{"profile":"google-chrome.desktop#Default","incognito":false}"#
+                            .to_string(),
+                },
+                ImportConflict {
+                    setting: "rule #1".to_string(),
+                    local_value: "firefox.desktop#work (incognito=false)".to_string(),
+                    imported_value: "google-chrome.desktop#Default (incognito=false)".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_settings_replace() {
+        let mut local_config = config(
+            r#"{
+                "hidden_apps": ["epiphany.desktop"],
+                "profile_order": ["firefox.desktop#work", "firefox.desktop#default"],
+                "rules": [{"url_pattern": "github.com", "profile": "firefox.desktop#work"}]
+            }"#,
+        );
+        let bundle = config(r#"{"ui": {"show_hotkeys": false}}"#);
+
+        let report = import_settings(
+            &mut local_config,
+            &bundle,
+            &["firefox.desktop".to_string()],
+            ImportMode::Replace,
+        );
+        assert_eq!(local_config.get_hidden_apps().is_empty(), true);
+        assert_eq!(local_config.get_profile_order().is_empty(), true);
+        assert_eq!(local_config.get_rules().is_empty(), true);
+        assert_eq!(local_config.get_ui_config().show_hotkeys, false);
+        assert_eq!(
+            report.summary_lines(),
+            vec![
+                r#"hidden_apps: replaced ["epiphany.desktop"] with []"#,
+                r#"profile_order: replaced ["firefox.desktop#work","firefox.desktop#default"] with []"#,
+                "rules: replaced 1 rules with 0 rules",
+            ]
+        );
+    }
+}
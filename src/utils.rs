@@ -1,6 +1,8 @@
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::{fs, u32};
 
 use druid::image;
@@ -8,22 +10,38 @@ use druid::image::imageops::FilterType;
 use druid::image::{ImageFormat, Rgba};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
+use crate::config_file::ConfigLock;
+use crate::config_formats::ConfigFormat;
+use crate::config_layers::ConfigLayer;
+use crate::config_migrations::{ConfigError, ParsedConfig, CONFIG_VERSION};
 #[cfg(target_os = "linux")]
 use crate::linux_utils;
 #[cfg(target_os = "macos")]
 use crate::macos_utils;
+use crate::profile_ids::IdAliases;
+use crate::rule_actions::{ProfileCandidates, RuleAction};
+use crate::rule_conditions::{ConfigEnvironment, ConfigSchedule, SourceAppPatterns};
+use crate::url_rule::{QueryParamCondition, UrlRegex};
 #[cfg(target_os = "windows")]
 use crate::windows_utils;
-use crate::{paths, InstalledBrowser, SupportedAppRepository};
+use crate::{
+    config_file, config_layers, config_migrations, paths, profile_ids, InstalledBrowser,
+    SupportedAppRepository,
+};
 
 #[cfg(target_os = "macos")]
 pub fn is_default_web_browser() -> bool {
     return macos_utils::is_default_web_browser();
 }
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(target_os = "linux")]
+pub fn is_default_web_browser() -> bool {
+    return linux_utils::is_default_web_browser();
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 pub fn is_default_web_browser() -> bool {
     return true;
 }
@@ -33,23 +51,197 @@ pub fn set_as_default_web_browser() -> bool {
     return macos_utils::set_default_web_browser();
 }
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(target_os = "linux")]
+pub fn set_as_default_web_browser() -> bool {
+    return linux_utils::set_default_web_browser();
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 pub fn set_as_default_web_browser() -> bool {
     return true;
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg(target_os = "macos")]
+pub fn get_hostname() -> Option<String> {
+    return macos_utils::get_hostname();
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_hostname() -> Option<String> {
+    return linux_utils::get_hostname();
+}
+
+#[cfg(target_os = "windows")]
+pub fn get_hostname() -> Option<String> {
+    return std::env::var("COMPUTERNAME").ok();
+}
+
+// macOS tells the source app along with the url (see LinkOpenedFromBundle),
+// elsewhere we have to find it out ourselves
+#[cfg(target_os = "linux")]
+pub fn detect_source_app() -> Option<String> {
+    return linux_utils::detect_source_app();
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect_source_app() -> Option<String> {
+    return None;
+}
+
+// restores the previous default web browser, if Browsers is the default
+#[cfg(target_os = "linux")]
+pub fn unset_as_default_web_browser() {
+    linux_utils::unset_default_web_browser();
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn unset_as_default_web_browser() {
+    info!("Restoring previous default web browser is not supported on this platform");
+}
+
+// Copies via the clipboard tool of the platform, because druid clipboard is available only
+// while the ui is running, and the tool keeps serving the clipboard after we exit
+#[cfg(target_os = "macos")]
+pub fn copy_to_clipboard(text: &str) -> bool {
+    return pipe_to_command("pbcopy", &[], text);
+}
+
+#[cfg(target_os = "linux")]
+pub fn copy_to_clipboard(text: &str) -> bool {
+    let is_wayland = std::env::var("WAYLAND_DISPLAY").is_ok();
+    if is_wayland && pipe_to_command("wl-copy", &[], text) {
+        return true;
+    }
+    return pipe_to_command("xclip", &["-selection", "clipboard"], text)
+        || pipe_to_command("xsel", &["--clipboard", "--input"], text);
+}
+
+#[cfg(target_os = "windows")]
+pub fn copy_to_clipboard(text: &str) -> bool {
+    return pipe_to_command("clip", &[], text);
+}
+
+#[cfg(target_os = "macos")]
+pub fn show_notification(title: &str, body: &str) {
+    let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+    let script = format!(
+        "display notification \"{}\" with title \"{}\"",
+        escape(body),
+        escape(title)
+    );
+    run_quietly("osascript", &["-e", script.as_str()]);
+}
+
+#[cfg(target_os = "linux")]
+pub fn show_notification(title: &str, body: &str) {
+    run_quietly("notify-send", &["--app-name=Browsers", title, body]);
+}
+
+#[cfg(target_os = "windows")]
+pub fn show_notification(title: &str, body: &str) {
+    info!(
+        "Notifications are not supported on this platform: {}: {}",
+        title, body
+    );
+}
+
+// for the user, when the default config is used because config files can't be read
+pub fn describe_config_error(error: &ConfigError) -> String {
+    return format!(
+        "{}\nRules and hidden profiles are not used until it is fixed.",
+        error
+    );
+}
+
+fn pipe_to_command(program: &str, args: &[&str], text: &str) -> bool {
+    let spawn_result = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if spawn_result.is_err() {
+        debug!("Could not run {}: {}", program, spawn_result.unwrap_err());
+        return false;
+    }
+    let mut child = spawn_result.unwrap();
+
+    // stdin is closed when dropped, so that the program knows the input has ended
+    let write_result = child.stdin.take().unwrap().write_all(text.as_bytes());
+    let wait_result = child.wait();
+    return write_result.is_ok() && wait_result.map_or(false, |status| status.success());
+}
+
+#[cfg(not(target_os = "windows"))]
+fn run_quietly(program: &str, args: &[&str]) {
+    let status_result = Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if status_result.is_err() {
+        warn!("Could not run {}: {}", program, status_result.unwrap_err());
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct Config {
+    // see config_migrations
+    version: u32,
     hidden_apps: Vec<String>,
     hidden_profiles: Vec<String>,
     profile_order: Vec<String>,
+    // defaults are not saved, so that they don't override system config files
+    #[serde(skip_serializing_if = "Option::is_none")]
     default_profile: Option<ProfileAndOptions>,
-    rules: Vec<ConfigRule>,
+    rules: Vec<ConfigRuleEntry>,
+    #[serde(skip_serializing_if = "is_default")]
+    rule_matching: RuleMatching,
+    #[serde(skip_serializing_if = "is_default")]
     ui: UIConfig,
+    // legacy ids which were replaced in this config, see profile_ids
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    legacy_id_aliases: IdAliases,
+    // config.json could not be read, so this config must not overwrite it
+    #[serde(skip)]
+    read_only: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            hidden_apps: vec![],
+            hidden_profiles: vec![],
+            profile_order: vec![],
+            default_profile: None,
+            rules: vec![],
+            rule_matching: RuleMatching::default(),
+            ui: UIConfig::default(),
+            legacy_id_aliases: IdAliases::new(),
+            read_only: false,
+        }
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    return *value == T::default();
+}
+
+// How to choose between rules which match the same url.
+// Rules with higher priority always win, this decides between rules with the same priority.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMatching {
+    // first rule in config wins
+    #[default]
+    FirstMatch,
+    // rule with the most specific url pattern wins, then the first in config
+    MostSpecific,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(default)]
 pub struct UIConfig {
     pub show_hotkeys: bool,
@@ -68,7 +260,7 @@ impl Default for UIConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(default)]
 pub struct ProfileAndOptions {
     pub profile: String,
@@ -78,10 +270,60 @@ pub struct ProfileAndOptions {
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(default)]
 pub struct ConfigRule {
-    pub source_app: Option<String>,
+    // glob or list of globs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_app: Option<SourceAppPatterns>,
+    // rule doesn't apply to links from these apps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_source_app: Option<SourceAppPatterns>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub url_pattern: Option<String>,
-    pub profile: String,
+    // alternative to url_pattern
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_regex: Option<UrlRegex>,
+    // conditions on query parameters, in addition to url_pattern/url_regex
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub query_params: BTreeMap<String, QueryParamCondition>,
+    // profile id, or a list of profile ids where the first existing one is used
+    pub profile: ProfileCandidates,
     pub incognito: bool,
+    // alternative to profile and incognito, e.g to copy the url or open it in several profiles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<RuleAction>,
+    // rules with higher priority are checked first, default is 0
+    pub priority: i32,
+    // rule applies only at these times
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<ConfigSchedule>,
+    // rule applies only on matching machines or sessions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<ConfigEnvironment>,
+}
+
+// Rule as it is in config. A rule which can't be read (e.g unknown action type) is kept
+// as it is, so it is skipped alone instead of failing the whole config,
+// and it is not lost when the config is saved.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ConfigRuleEntry {
+    Valid(ConfigRule),
+    Invalid(serde_json::Value),
+}
+
+impl ConfigRuleEntry {
+    // None if the rule can't be read
+    pub fn get_rule(&self) -> Option<&ConfigRule> {
+        return match self {
+            ConfigRuleEntry::Valid(rule) => Some(rule),
+            ConfigRuleEntry::Invalid(_) => None,
+        };
+    }
+}
+
+impl From<ConfigRule> for ConfigRuleEntry {
+    fn from(rule: ConfigRule) -> Self {
+        return ConfigRuleEntry::Valid(rule);
+    }
 }
 
 impl Config {
@@ -128,21 +370,110 @@ impl Config {
         &self.profile_order
     }
 
+    pub fn set_hidden_apps(&mut self, hidden_apps: Vec<String>) {
+        self.hidden_apps = hidden_apps;
+    }
+
+    pub fn set_hidden_profiles(&mut self, hidden_profiles: Vec<String>) {
+        self.hidden_profiles = hidden_profiles;
+    }
+
     pub fn set_profile_order(&mut self, profile_order: &Vec<String>) {
         self.profile_order = profile_order.clone();
     }
 
-    pub fn get_rules(&self) -> &Vec<ConfigRule> {
+    pub fn get_rules(&self) -> &Vec<ConfigRuleEntry> {
         return &self.rules;
     }
 
+    pub fn set_rules(&mut self, rules: Vec<ConfigRuleEntry>) {
+        self.rules = rules;
+    }
+
+    pub fn add_rule(&mut self, rule: ConfigRule) {
+        self.rules.push(ConfigRuleEntry::from(rule));
+    }
+
+    pub fn get_rule_matching(&self) -> RuleMatching {
+        return self.rule_matching;
+    }
+
     pub fn get_default_profile(&self) -> &Option<ProfileAndOptions> {
         return &self.default_profile;
     }
 
+    pub fn set_default_profile(&mut self, default_profile: Option<ProfileAndOptions>) {
+        self.default_profile = default_profile;
+    }
+
     pub fn get_ui_config(&self) -> &UIConfig {
         return &self.ui;
     }
+
+    pub fn set_ui_config(&mut self, ui_config: UIConfig) {
+        self.ui = ui_config;
+    }
+
+    // copy with only the settings which are moved between machines, see settings_bundle
+    pub fn get_exported_settings(&self) -> Config {
+        return Config {
+            hidden_apps: self.hidden_apps.clone(),
+            hidden_profiles: self.hidden_profiles.clone(),
+            profile_order: self.profile_order.clone(),
+            default_profile: self.default_profile.clone(),
+            rules: self.rules.clone(),
+            ui: self.ui.clone(),
+            ..Config::default()
+        };
+    }
+
+    // Replaces legacy ids with stable ids, returns how many ids were replaced.
+    // Aliases which were used are kept in the config, so the same legacy ids still resolve
+    // after the app moves again (e.g in rules copied from an older config).
+    pub fn migrate_legacy_ids(&mut self, aliases: &IdAliases) -> usize {
+        let mut all_aliases = self.legacy_id_aliases.clone();
+        all_aliases.extend(aliases.clone());
+
+        let mut used_aliases = IdAliases::new();
+        let mut renamed_count = 0;
+        self.for_each_profile_id_mut(&mut |id| {
+            if profile_ids::rename_id(id, &all_aliases, &mut used_aliases) {
+                renamed_count += 1;
+            }
+        });
+        if renamed_count == 0 {
+            return 0;
+        }
+
+        profile_ids::remove_duplicates(&mut self.hidden_apps);
+        profile_ids::remove_duplicates(&mut self.hidden_profiles);
+        profile_ids::remove_duplicates(&mut self.profile_order);
+        self.legacy_id_aliases.extend(used_aliases);
+        return renamed_count;
+    }
+
+    // every app and profile id in the config
+    pub(crate) fn for_each_profile_id_mut(&mut self, f: &mut impl FnMut(&mut String)) {
+        self.hidden_apps
+            .iter_mut()
+            .chain(self.hidden_profiles.iter_mut())
+            .chain(self.profile_order.iter_mut())
+            .for_each(|id| f(id));
+
+        if let Some(ref mut default_profile) = self.default_profile {
+            f(&mut default_profile.profile);
+        }
+
+        // ids in rules which can't be read are left as they are
+        for rule_entry in self.rules.iter_mut() {
+            if let ConfigRuleEntry::Valid(ref mut rule) = rule_entry {
+                rule.profile.for_each_id_mut(f);
+                if let Some(ref mut action) = rule.action {
+                    action.for_each_profile_id_mut(f);
+                }
+            }
+        }
+    }
 }
 
 pub struct OSAppFinder {
@@ -219,45 +550,215 @@ impl OSAppFinder {
         return self.inner.get_app_repository();
     }
 
-    pub(crate) fn save_installed_browsers_config(&self, config: &Config) {
+    // Prefer update_installed_browsers_config, which also prevents overwriting changes made
+    // by other instances at the same time
+    pub(crate) fn save_installed_browsers_config(
+        &self,
+        config: &Config,
+    ) -> Result<(), ConfigError> {
+        if config.read_only {
+            return Err(ConfigError::ReadOnly);
+        }
+
         let config_root_dir = paths::get_config_root_dir();
-        fs::create_dir_all(config_root_dir.as_path()).unwrap();
-        let config_json_path = paths::get_config_json_path();
-        let buffer = File::create(config_json_path).unwrap();
-        serde_json::to_writer_pretty(buffer, config).unwrap();
+        fs::create_dir_all(config_root_dir.as_path())?;
+        let config_path = paths::get_config_file_path();
+        let format = ConfigFormat::from_path(config_path.as_path()).unwrap_or(ConfigFormat::Json);
+        // comments and formatting of the previous file are kept, if the format supports it
+        let previous_contents_maybe = fs::read_to_string(config_path.as_path()).ok();
+        let contents_result = format.serialize_config(config, previous_contents_maybe.as_deref());
+        if contents_result.is_err() {
+            let error = contents_result.unwrap_err();
+            show_notification("Browsers could not save its config", error.to_string().as_str());
+            return Err(error);
+        }
+
+        let contents = contents_result.unwrap();
+        config_file::write_atomically(config_path.as_path(), contents.as_str())?;
+        return Ok(());
     }
 
-    pub(crate) fn get_installed_browsers_config(&self) -> Config {
+    // Reads, changes and saves the user's config file while holding a lock on it.
+    // Not saved if nothing changed, so backups are not rotated needlessly,
+    // unless the file doesn't exist yet or is from an older version.
+    // config.json.lock is used for every format, so the lock doesn't change with the file.
+    pub(crate) fn update_installed_browsers_config<T>(
+        &self,
+        update: impl FnOnce(&mut Config) -> T,
+    ) -> Result<T, ConfigError> {
         let config_root_dir = paths::get_config_root_dir();
-        fs::create_dir_all(config_root_dir.as_path()).unwrap();
-        let config_json_path = paths::get_config_json_path();
-        info!("Config: {}", config_json_path.display());
+        fs::create_dir_all(config_root_dir.as_path())?;
+        let _config_lock = ConfigLock::acquire(paths::get_config_json_path().as_path())?;
+
+        let config_path = paths::get_config_file_path();
+        let format = ConfigFormat::from_path(config_path.as_path()).unwrap_or(ConfigFormat::Json);
+        let parsed = self.load_user_config_file()?;
+        // formats which can't be saved are migrated again every time they are read
+        let is_migrated = parsed.version < CONFIG_VERSION && format.can_save();
+        if is_migrated {
+            info!(
+                "Migrating config from version {} to {}",
+                parsed.version, CONFIG_VERSION
+            );
+            self.backup_installed_browsers_config(
+                format!("config.v{}.json", parsed.version).as_str(),
+            );
+        }
 
-        if config_json_path.exists() {
-            // Open the file in read-only mode with buffer.
-            let file = File::open(config_json_path.as_path()).unwrap();
-            let reader = BufReader::new(file);
-            let result: Result<Config, _> = serde_json::from_reader(reader);
+        let mut config = parsed.config;
+        let config_json_before = serde_json::to_string(&config)?;
+        let result = update(&mut config);
+        let is_changed = serde_json::to_string(&config)? != config_json_before;
+        if is_changed || is_migrated || !config_path.exists() {
+            self.save_installed_browsers_config(&config)?;
+        }
+        return Ok(result);
+    }
 
-            if result.is_err() {
-                // we can't read in config as valid config,
-                // just in case copy the config file for debugging
+    // Creates the user's config file, or saves it in the current version if it's from
+    // an older one. Reading config never writes, so that it doesn't need the lock.
+    pub(crate) fn create_or_migrate_user_config(&self) {
+        let update_result = self.update_installed_browsers_config(|_| {});
+        if update_result.is_err() {
+            warn!(
+                "Could not create or migrate config: {}",
+                update_result.unwrap_err()
+            );
+        }
+    }
 
-                let corrupted_config_json_path = config_root_dir.join("config.corrupted.json");
-                fs::copy(config_json_path.as_path(), corrupted_config_json_path).ok();
+    // backup_filename gets the extension of the config file, e.g config.v0.toml
+    pub(crate) fn backup_installed_browsers_config(&self, backup_filename: &str) {
+        let config_path = paths::get_config_file_path();
+        let backup_path = Self::get_config_backup_path(config_path.as_path(), backup_filename);
+        let copy_result = fs::copy(config_path.as_path(), backup_path.as_path());
+        if copy_result.is_err() {
+            warn!(
+                "Could not back up config to {}: {}",
+                backup_path.display(),
+                copy_result.unwrap_err()
+            );
+        }
+    }
 
-                // just use empty config, but don't write it yet, it will be overwritten on first
-                // change in config
-                return Config::default();
-            }
-            let config = result.unwrap();
-            return config;
-        } else {
+    // Same as backup_installed_browsers_config(), but an existing backup is kept,
+    // e.g the config from before a one time migration
+    pub(crate) fn backup_installed_browsers_config_once(&self, backup_filename: &str) {
+        let config_path = paths::get_config_file_path();
+        let backup_path = Self::get_config_backup_path(config_path.as_path(), backup_filename);
+        if backup_path.exists() {
+            return;
+        }
+        self.backup_installed_browsers_config(backup_filename);
+    }
+
+    fn get_config_backup_path(config_path: &Path, backup_filename: &str) -> PathBuf {
+        let mut backup_path = paths::get_config_root_dir().join(backup_filename);
+        if let Some(extension) = config_path.extension() {
+            backup_path.set_extension(extension);
+        }
+        return backup_path;
+    }
+
+    // Falls back to the default config if config files can't be read.
+    // The fallback config is never saved, so the broken config file can still be fixed.
+    // The error is only logged, see get_installed_browsers_config_and_error().
+    pub(crate) fn get_installed_browsers_config(&self) -> Config {
+        let (config, _) = self.get_installed_browsers_config_and_error();
+        return config;
+    }
+
+    // Same as get_installed_browsers_config(), but also returns the error,
+    // so that it can be shown to the user once
+    pub(crate) fn get_installed_browsers_config_and_error(&self) -> (Config, Option<ConfigError>) {
+        let config_result = self.load_installed_browsers_config();
+        if config_result.is_err() {
+            let error = config_result.unwrap_err();
+            warn!("Could not read config: {}", error);
+
+            let fallback_config = Config {
+                read_only: true,
+                ..Config::default()
+            };
+            return (fallback_config, Some(error));
+        }
+
+        return (config_result.unwrap(), None);
+    }
+
+    // Strict version of get_installed_browsers_config, which returns errors.
+    // Merges system config files with the user's config file, see config_layers.
+    pub(crate) fn load_installed_browsers_config(&self) -> Result<Config, ConfigError> {
+        let user_config = self.load_user_config_file()?;
+        let (mut layers, system_errors) = config_layers::load_system_layers();
+        for system_error in system_errors {
+            warn!("Skipping system config file {}", system_error);
+        }
+        if layers.is_empty() {
+            return Ok(user_config.config);
+        }
+
+        layers.push(ConfigLayer {
+            path: paths::get_config_file_path(),
+            value: user_config.value,
+            is_system: false,
+        });
+        let (merged_value, ignored_changes) = config_layers::merge_layers(&layers);
+        for ignored_change in ignored_changes {
+            warn!(
+                "Ignoring {} in {}, it is locked by system config",
+                ignored_change.key,
+                ignored_change.path.display()
+            );
+        }
+
+        let config: Config = serde_json::from_value(merged_value)?;
+        return Ok(config);
+    }
+
+    // Profiles hidden by system config files can't be restored, as only the user's file changes
+    pub(crate) fn is_hidden_by_system_config(&self, profile_id: &str) -> bool {
+        let (layers, _) = config_layers::load_system_layers();
+        return config_layers::is_hidden_by_system_layers(&layers, profile_id);
+    }
+
+    // Only the user's config file, without system config files.
+    // This is what Browsers changes, e.g when hiding a profile.
+    pub(crate) fn load_user_config(&self) -> Result<Config, ConfigError> {
+        return self
+            .load_user_config_file()
+            .map(|user_config| user_config.config);
+    }
+
+    // Only reads, see update_installed_browsers_config() for creating and migrating the file
+    fn load_user_config_file(&self) -> Result<ParsedConfig, ConfigError> {
+        let config_path = paths::get_config_file_path();
+        info!("Config: {}", config_path.display());
+
+        if !config_path.exists() {
             let config = Config::default();
-            let buffer = File::create(config_json_path.as_path()).unwrap();
-            serde_json::to_writer_pretty(buffer, &config).unwrap();
-            return config;
+            return Ok(ParsedConfig {
+                value: serde_json::to_value(&config)?,
+                config: config,
+                version: CONFIG_VERSION,
+            });
         }
+
+        let config_json_path = paths::get_config_json_path();
+        if config_path != config_json_path && config_json_path.exists() {
+            warn!(
+                "{} is not used, as {} is used instead",
+                config_json_path.display(),
+                config_path.display()
+            );
+        }
+
+        let format = ConfigFormat::from_path(config_path.as_path()).unwrap_or(ConfigFormat::Json);
+        let contents = fs::read_to_string(config_path.as_path())
+            .map_err(|error| ConfigError::from(error).in_file(config_path.clone()))?;
+        let parsed = config_migrations::parse_config(contents.as_str(), format)
+            .map_err(|error| error.in_file(config_path.clone()))?;
+        return Ok(parsed);
     }
 
     pub(crate) fn get_installed_browsers_cached(
@@ -444,6 +444,13 @@ pub fn get_this_app_config_root_dir() -> PathBuf {
     return get_this_app_config_local_dir().join("config");
 }
 
+// C:\ProgramData\software.Browsers
+// for config managed by administrators
+pub fn get_this_app_system_config_dirs() -> Vec<PathBuf> {
+    let program_data_dir = std::env::var("ProgramData").unwrap_or("C:\\ProgramData".to_string());
+    return vec![PathBuf::from(program_data_dir).join(APP_DIR_NAME)];
+}
+
 // For resources (e.g translations)
 // C:\Users\Alice\AppData\Local\Programs\software.Browsers\resources
 // %ProgramFiles%\software.Browsers\resources